@@ -0,0 +1,263 @@
+use std::fmt::Write;
+
+use crate::token::{Location, Span, Token};
+
+// ANSI escape codes used when colors are enabled
+const BOLD_RED: &str = "\x1b[1;31m";
+const BOLD_BLUE: &str = "\x1b[1;34m";
+const BOLD: &str = "\x1b[1m";
+const RESET: &str = "\x1b[0m";
+
+// An error to report to the user, pointing to
+// the region of the source that caused it
+pub struct Diagnostic {
+    pub message: String,
+    pub line: u64,
+    pub column: u64,
+    pub span: Span,
+    pub notes: Vec<String>,
+    pub help: Option<String>,
+}
+
+impl Diagnostic {
+    pub fn new(message: &str, token: &Token) -> Self {
+        Self::at(message, token.location())
+    }
+
+    pub fn at(message: &str, location: Location) -> Self {
+        Diagnostic {
+            message: message.to_string(),
+            line: location.line,
+            column: location.column,
+            span: location.span,
+            notes: Vec::new(),
+            help: None,
+        }
+    }
+
+    pub fn with_note(mut self, note: &str) -> Self {
+        self.notes.push(note.to_string());
+        self
+    }
+
+    pub fn with_help(mut self, help: Option<&str>) -> Self {
+        self.help = help.map(|help| help.to_string());
+        self
+    }
+}
+
+// Renders the diagnostics in the style of rustc:
+//
+// error: expected expression
+//  --> script.lox:3:10
+//   |
+// 3 | print 1 +;
+//   |          ^
+//   = help: ...
+pub struct Renderer<'a> {
+    file_name: &'a str,
+    source: &'a str,
+    color: bool,
+}
+
+impl<'a> Renderer<'a> {
+    pub fn new(file_name: &'a str, source: &'a str, color: bool) -> Self {
+        Renderer {
+            file_name,
+            source,
+            color,
+        }
+    }
+
+    pub fn file_name(&self) -> &str {
+        self.file_name
+    }
+
+    // print the diagnostic on the standard error
+    pub fn emit(&self, diagnostic: &Diagnostic) {
+        eprint!("{}", self.render(diagnostic));
+    }
+
+    pub fn render(&self, diagnostic: &Diagnostic) -> String {
+        let gutter_width = diagnostic.line.to_string().len();
+        let gutter = " ".repeat(gutter_width);
+        let mut output = String::new();
+
+        // writing to a String never fails
+        let _ = writeln!(
+            output,
+            "{}: {}",
+            self.paint(BOLD_RED, "error"),
+            self.paint(BOLD, &diagnostic.message)
+        );
+        let _ = writeln!(
+            output,
+            "{}{} {}:{}:{}",
+            gutter,
+            self.paint(BOLD_BLUE, "-->"),
+            self.file_name,
+            diagnostic.line,
+            diagnostic.column
+        );
+
+        if let Some((line, underline)) = self.snippet(diagnostic.span) {
+            let bar = self.paint(BOLD_BLUE, "|");
+            let _ = writeln!(output, "{} {}", gutter, bar);
+            let _ = writeln!(
+                output,
+                "{} {} {}",
+                self.paint(BOLD_BLUE, &diagnostic.line.to_string()),
+                bar,
+                line
+            );
+            let _ = writeln!(
+                output,
+                "{} {} {}",
+                gutter,
+                bar,
+                self.paint(BOLD_RED, &underline)
+            );
+        }
+
+        for note in &diagnostic.notes {
+            // the following lines of a note are indented under the first one
+            let mut lines = note.lines();
+            let _ = writeln!(
+                output,
+                "{} {} {}: {}",
+                gutter,
+                self.paint(BOLD_BLUE, "="),
+                self.paint(BOLD, "note"),
+                lines.next().unwrap_or_default()
+            );
+            for line in lines {
+                let _ = writeln!(output, "{}         {}", gutter, line);
+            }
+        }
+        if let Some(help) = &diagnostic.help {
+            let _ = writeln!(
+                output,
+                "{} {} {}: {}",
+                gutter,
+                self.paint(BOLD_BLUE, "="),
+                self.paint(BOLD, "help"),
+                help
+            );
+        }
+        // a blank line separates the reports from each other
+        output.push('\n');
+        output
+    }
+
+    // The source line where the span begins and the carets
+    // underlining the span. A span that goes over multiple lines
+    // is underlined only up to the end of its first line.
+    fn snippet(&self, span: Span) -> Option<(&'a str, String)> {
+        let before = self.source.get(..span.start)?;
+        let line_start = before.rfind('\n').map_or(0, |newline| newline + 1);
+        let line_end = self.source[span.start..]
+            .find('\n')
+            .map_or(self.source.len(), |newline| span.start + newline);
+        let line = self.source[line_start..line_end].trim_end_matches('\r');
+
+        // keep the tabs, so that the carets line up with the source
+        let padding: String = self.source[line_start..span.start]
+            .chars()
+            .map(|c| if c == '\t' { '\t' } else { ' ' })
+            .collect();
+        let width = self
+            .source
+            .get(span.start..span.end.min(line_end))
+            .map_or(0, |lexeme| lexeme.chars().count())
+            // tokens without a lexeme, like the end of
+            // file, still get a caret pointing at them
+            .max(1);
+
+        Some((line, format!("{}{}", padding, "^".repeat(width))))
+    }
+
+    fn paint(&self, style: &str, text: &str) -> String {
+        if self.color {
+            format!("{}{}{}", style, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // render an error spanning the given bytes, with colors off
+    fn render(source: &str, line: u64, column: u64, start: usize, end: usize) -> String {
+        let location = Location {
+            line,
+            column,
+            span: Span { start, end },
+        };
+        Renderer::new("test.lox", source, false).render(&Diagnostic::at("oops", location))
+    }
+
+    #[test]
+    fn underlines_the_whole_span() {
+        assert_eq!(
+            render("print 1 + abc;", 1, 11, 10, 13),
+            "error: oops\n --> test.lox:1:11\n  |\n1 | print 1 + abc;\n  |           ^^^\n\n"
+        );
+    }
+
+    #[test]
+    fn points_at_the_end_of_file() {
+        assert_eq!(
+            render("print 1 +", 1, 10, 9, 9),
+            "error: oops\n --> test.lox:1:10\n  |\n1 | print 1 +\n  |          ^\n\n"
+        );
+    }
+
+    #[test]
+    fn keeps_the_tabs_before_the_span() {
+        assert_eq!(
+            render("\tprint x;", 1, 8, 7, 8),
+            "error: oops\n --> test.lox:1:8\n  |\n1 | \tprint x;\n  | \t      ^\n\n"
+        );
+    }
+
+    #[test]
+    fn counts_chars_not_bytes() {
+        // "é" takes two bytes but a single column
+        assert_eq!(
+            render("print \"é\" + nil;", 1, 13, 13, 16),
+            "error: oops\n --> test.lox:1:13\n  |\n1 | print \"é\" + nil;\n  |             ^^^\n\n"
+        );
+        assert_eq!(
+            render("print \"é\";", 1, 7, 6, 10),
+            "error: oops\n --> test.lox:1:7\n  |\n1 | print \"é\";\n  |       ^^^\n\n"
+        );
+    }
+
+    #[test]
+    fn clamps_multi_line_spans_to_the_first_line() {
+        assert_eq!(
+            render("print \"a\nbc\";\n", 1, 7, 6, 12),
+            "error: oops\n --> test.lox:1:7\n  |\n1 | print \"a\n  |       ^^\n\n"
+        );
+    }
+
+    #[test]
+    fn renders_notes_and_help() {
+        let source = format!("{}x;", "\n".repeat(9));
+        let location = Location {
+            line: 10,
+            column: 1,
+            span: Span { start: 9, end: 10 },
+        };
+        let diagnostic = Diagnostic::at("oops", location)
+            .with_note("first line\nsecond line")
+            .with_help(Some("try this"));
+        assert_eq!(
+            Renderer::new("test.lox", &source, false).render(&diagnostic),
+            "error: oops\n  --> test.lox:10:1\n   |\n10 | x;\n   | ^\n   = note: first line\n           second line\n   = help: try this\n\n"
+        );
+    }
+}
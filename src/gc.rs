@@ -0,0 +1,180 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashSet;
+use std::mem;
+use std::rc::{Rc, Weak};
+
+// The objects are still owned by reference counting, which frees most
+// of them as soon as they become unreachable. The collector only has
+// to find the reference cycles, which counting never frees: starting
+// from the roots of the interpreter it marks the reachable objects,
+// then it clears the references held by all the other ones, breaking
+// the cycles they are part of so that counting can free them too.
+//
+// Only the objects that can close a cycle, because the references
+// they hold change after they are created, have to be tracked:
+// environments, instances and upvalues, plus the classes of the VM,
+// which get their methods one at a time after being created. The
+// classes of the tree walker get all their methods at once, so they
+// are not tracked.
+
+// the heap size that triggers the first collection
+const DEFAULT_THRESHOLD: usize = 1024 * 1024;
+// how much the heap can grow after a collection before the next one
+const GROWTH_FACTOR: usize = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Options {
+    // collect on every allocation, so that the objects
+    // the interpreter forgets to root are cleared right away
+    pub stress: bool,
+    // the size in bytes the heap has to reach before the first
+    // collection. Later collections wait for the heap to grow
+    // to GROWTH_FACTOR times the size that survived the previous one
+    pub threshold: usize,
+}
+
+impl Default for Options {
+    fn default() -> Self {
+        Options {
+            stress: false,
+            threshold: DEFAULT_THRESHOLD,
+        }
+    }
+}
+
+// An object managed by the collector
+pub trait Trace {
+    // mark the tracked objects this object references
+    fn trace(&self, tracer: &mut Tracer);
+    // drop the references held by the object, once it's unreachable
+    fn clear(&self);
+}
+
+// The state of the mark phase
+pub struct Tracer {
+    // the addresses of the objects found reachable so far
+    marked: HashSet<usize>,
+    // the marked objects whose references have yet to be traced
+    gray: Vec<Rc<dyn Trace>>,
+}
+
+impl Tracer {
+    pub fn mark<T: Trace + 'static>(&mut self, object: &Rc<T>) {
+        if self.marked.insert(address(object)) {
+            self.gray.push(Rc::clone(object) as Rc<dyn Trace>);
+        }
+    }
+}
+
+fn address<T: ?Sized>(object: &Rc<T>) -> usize {
+    Rc::as_ptr(object) as *const () as usize
+}
+
+struct Tracked {
+    object: Weak<dyn Trace>,
+    address: usize,
+    size: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Stats {
+    // the size of the tracked objects not yet reclaimed
+    pub bytes_allocated: usize,
+    pub collections: usize,
+}
+
+// The registry of the tracked objects
+pub struct Heap {
+    objects: RefCell<Vec<Tracked>>,
+    bytes_allocated: Cell<usize>,
+    next_collection: Cell<usize>,
+    collections: Cell<usize>,
+    // set by the allocations, the collection itself runs when the
+    // interpreter reaches a point where all its live values are rooted
+    due: Cell<bool>,
+    options: Options,
+}
+
+impl Heap {
+    pub fn new(options: Options) -> Self {
+        Heap {
+            objects: RefCell::new(Vec::new()),
+            bytes_allocated: Cell::new(0),
+            next_collection: Cell::new(options.threshold),
+            collections: Cell::new(0),
+            due: Cell::new(false),
+            options,
+        }
+    }
+
+    // the size of an object is approximated by the size of its
+    // allocation, without the buffers of the collections it owns
+    pub fn allocate<T: Trace + 'static>(&self, object: T) -> Rc<T> {
+        let object = Rc::new(object);
+        let size = mem::size_of::<T>();
+        self.objects.borrow_mut().push(Tracked {
+            object: Rc::downgrade(&(Rc::clone(&object) as Rc<dyn Trace>)),
+            address: address(&object),
+            size,
+        });
+        let bytes_allocated = self.bytes_allocated.get() + size;
+        self.bytes_allocated.set(bytes_allocated);
+        if self.options.stress || bytes_allocated > self.next_collection.get() {
+            self.due.set(true);
+        }
+        object
+    }
+
+    pub fn collection_due(&self) -> bool {
+        self.due.get()
+    }
+
+    // mark everything reachable from the roots, then
+    // clear the tracked objects that were not marked
+    pub fn collect(&self, trace_roots: impl FnOnce(&mut Tracer)) {
+        let mut tracer = Tracer {
+            marked: HashSet::new(),
+            gray: Vec::new(),
+        };
+        trace_roots(&mut tracer);
+        while let Some(object) = tracer.gray.pop() {
+            object.trace(&mut tracer);
+        }
+
+        // the objects are cleared after the sweep, so that the
+        // frees they cause don't happen while the registry is borrowed
+        let mut unreachable = Vec::new();
+        let mut freed = 0;
+        self.objects.borrow_mut().retain(|tracked| {
+            let Some(object) = tracked.object.upgrade() else {
+                // already freed by reference counting
+                freed += tracked.size;
+                return false;
+            };
+            if tracer.marked.contains(&tracked.address) {
+                return true;
+            }
+            unreachable.push(object);
+            freed += tracked.size;
+            false
+        });
+        for object in &unreachable {
+            object.clear();
+        }
+        drop(unreachable);
+
+        let bytes_allocated = self.bytes_allocated.get() - freed;
+        self.bytes_allocated.set(bytes_allocated);
+        self.next_collection
+            .set((bytes_allocated * GROWTH_FACTOR).max(self.options.threshold));
+        self.collections.set(self.collections.get() + 1);
+        self.due.set(false);
+    }
+
+    pub fn stats(&self) -> Stats {
+        Stats {
+            bytes_allocated: self.bytes_allocated.get(),
+            collections: self.collections.get(),
+        }
+    }
+}
@@ -0,0 +1,106 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+// A string stored once in the symbol table. Two symbols are equal
+// exactly when their strings are, so comparing and hashing them,
+// e.g. to look up a variable, doesn't depend on the string length
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+// the names the interpreters look up by themselves,
+// interned first so that they have a fixed symbol
+const PREDEFINED: [&str; 3] = ["init", "this", "super"];
+
+impl Symbol {
+    pub const INIT: Symbol = Symbol(0);
+    pub const THIS: Symbol = Symbol(1);
+    pub const SUPER: Symbol = Symbol(2);
+
+    pub fn intern(string: &str) -> Symbol {
+        INTERNER.with(|interner| interner.borrow_mut().intern(string))
+    }
+
+    // the interned string, shared by everything that refers to it
+    pub fn string(self) -> Rc<str> {
+        INTERNER.with(|interner| Rc::clone(&interner.borrow().strings[self.0 as usize]))
+    }
+}
+
+// The symbol table. The strings are never removed, the same
+// identifiers and literals are used for the whole session
+struct Interner {
+    symbols: HashMap<Rc<str>, Symbol>,
+    strings: Vec<Rc<str>>,
+}
+
+impl Interner {
+    fn new() -> Self {
+        let mut interner = Interner {
+            symbols: HashMap::new(),
+            strings: Vec::new(),
+        };
+        for string in PREDEFINED {
+            interner.intern(string);
+        }
+        interner
+    }
+
+    fn intern(&mut self, string: &str) -> Symbol {
+        if let Some(symbol) = self.symbols.get(string) {
+            return *symbol;
+        }
+        let symbol = Symbol(self.strings.len() as u32);
+        let string: Rc<str> = Rc::from(string);
+        self.strings.push(Rc::clone(&string));
+        self.symbols.insert(string, symbol);
+        symbol
+    }
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner::new());
+}
+
+impl PartialEq<&str> for Symbol {
+    fn eq(&self, other: &&str) -> bool {
+        &*self.string() == *other
+    }
+}
+
+impl fmt::Display for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.string())
+    }
+}
+
+impl fmt::Debug for Symbol {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equal_strings_share_the_symbol() {
+        let symbol = Symbol::intern("counter");
+        assert_eq!(symbol, Symbol::intern("counter"));
+        assert_ne!(symbol, Symbol::intern("count"));
+        assert!(Rc::ptr_eq(
+            &symbol.string(),
+            &Symbol::intern("counter").string()
+        ));
+        assert_eq!(symbol, "counter");
+    }
+
+    #[test]
+    fn predefined_symbols() {
+        assert_eq!(Symbol::intern("init"), Symbol::INIT);
+        assert_eq!(Symbol::intern("this"), Symbol::THIS);
+        assert_eq!(Symbol::intern("super"), Symbol::SUPER);
+    }
+}
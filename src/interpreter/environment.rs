@@ -0,0 +1,155 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::error::RuntimeError;
+use super::value::LiteralValue;
+use crate::gc::{Trace, Tracer};
+use crate::interner::Symbol;
+use crate::token::Token;
+
+// The bindings that associate variables to values.
+// Each block creates a new environment chained to the
+// enclosing one, up to the global environment that has none.
+pub struct Environment {
+    values: HashMap<Symbol, LiteralValue>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+}
+
+impl Environment {
+    pub fn new() -> Self {
+        Environment {
+            values: HashMap::new(),
+            enclosing: None,
+        }
+    }
+
+    pub fn new_enclosed(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Environment {
+            values: HashMap::new(),
+            enclosing: Some(enclosing),
+        }
+    }
+
+    // a variable definition binds a new name to a value.
+    // Redefining an existing variable is allowed and simply
+    // overwrites the previous value.
+    pub fn define(&mut self, name: Symbol, value: LiteralValue) {
+        self.values.insert(name, value);
+    }
+
+    // variables are looked up from the innermost scope outwards,
+    // so an inner declaration shadows the outer ones
+    pub fn get(&self, name: &Token) -> Result<LiteralValue, RuntimeError> {
+        if let Some(value) = self.values.get(&name.name()) {
+            return Ok(value.clone());
+        }
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow().get(name),
+            None => Err(RuntimeError::new(
+                format!("undefined variable '{}'", name.name()),
+                name,
+            )),
+        }
+    }
+
+    // the environment `distance` hops up the chain
+    pub fn ancestor(
+        environment: &Rc<RefCell<Environment>>,
+        distance: usize,
+    ) -> Rc<RefCell<Environment>> {
+        let mut ancestor = Rc::clone(environment);
+        for _ in 0..distance {
+            let enclosing = ancestor
+                .borrow()
+                .enclosing
+                .clone()
+                .expect("the resolver computed a distance deeper than the environment chain");
+            ancestor = enclosing;
+        }
+        ancestor
+    }
+
+    // look up a variable declared directly in this environment
+    pub fn get_local(&self, name: Symbol) -> Option<LiteralValue> {
+        self.values.get(&name).cloned()
+    }
+
+    // get the variable from the environment `distance` hops
+    // up the chain, where the resolver found its declaration
+    pub fn get_at(&self, distance: usize, name: &Token) -> Result<LiteralValue, RuntimeError> {
+        if distance == 0 {
+            return match self.values.get(&name.name()) {
+                Some(value) => Ok(value.clone()),
+                None => Err(RuntimeError::new(
+                    format!("undefined variable '{}'", name.name()),
+                    name,
+                )),
+            };
+        }
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow().get_at(distance - 1, name),
+            None => Err(RuntimeError::new(
+                format!("undefined variable '{}'", name.name()),
+                name,
+            )),
+        }
+    }
+
+    pub fn assign_at(
+        &mut self,
+        distance: usize,
+        name: &Token,
+        value: LiteralValue,
+    ) -> Result<(), RuntimeError> {
+        if distance == 0 {
+            return self.assign(name, value);
+        }
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().assign_at(distance - 1, name, value),
+            None => Err(RuntimeError::new(
+                format!("undefined variable '{}'", name.name()),
+                name,
+            )),
+        }
+    }
+
+    // assignment is not allowed to create a new variable
+    pub fn assign(&mut self, name: &Token, value: LiteralValue) -> Result<(), RuntimeError> {
+        if let Some(slot) = self.values.get_mut(&name.name()) {
+            *slot = value;
+            return Ok(());
+        }
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().assign(name, value),
+            None => Err(RuntimeError::new(
+                format!("undefined variable '{}'", name.name()),
+                name,
+            )),
+        }
+    }
+}
+
+impl Default for Environment {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Trace for RefCell<Environment> {
+    fn trace(&self, tracer: &mut Tracer) {
+        let environment = self.borrow();
+        for value in environment.values.values() {
+            value.trace(tracer);
+        }
+        if let Some(enclosing) = &environment.enclosing {
+            tracer.mark(enclosing);
+        }
+    }
+
+    fn clear(&self) {
+        let mut environment = self.borrow_mut();
+        environment.values.clear();
+        environment.enclosing = None;
+    }
+}
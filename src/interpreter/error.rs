@@ -0,0 +1,91 @@
+use crate::token::{Location, Token};
+
+// An error raised while running the program, by either backend
+pub struct RuntimeError {
+    pub message: String,
+    pub location: Location,
+    // the function calls that were active when the error
+    // was raised. None for errors raised by top-level code.
+    // Boxed to keep the error small, as it's returned everywhere
+    pub trace: Option<Box<StackTrace>>,
+}
+
+impl RuntimeError {
+    pub fn new(message: String, token: &Token) -> Self {
+        Self::at(message, token.location())
+    }
+
+    pub fn at(message: String, location: Location) -> Self {
+        RuntimeError {
+            message,
+            location,
+            trace: None,
+        }
+    }
+}
+
+// The frames of the call stack, starting from the innermost one
+pub struct StackTrace(pub Vec<StackFrame>);
+
+impl StackTrace {
+    // one line per frame, with the identical frames of a recursion
+    // collapsed into the first one, followed by their count
+    pub fn lines(&self, file_name: &str) -> Vec<String> {
+        let mut lines = Vec::new();
+        for run in self
+            .0
+            .chunk_by(|left, right| left.function == right.function && left.line == right.line)
+        {
+            lines.push(format!(
+                "at {} ({}:{})",
+                run[0].function, file_name, run[0].line
+            ));
+            match run.len() {
+                1 => {}
+                2 => lines.push("... 1 more frame like this".to_string()),
+                length => lines.push(format!("... {} more frames like this", length - 1)),
+            }
+        }
+        lines
+    }
+}
+
+// A function that was executing and the line it was executing
+pub struct StackFrame {
+    pub function: String,
+    pub line: u64,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn frame(function: &str, line: u64) -> StackFrame {
+        StackFrame {
+            function: function.to_string(),
+            line,
+        }
+    }
+
+    #[test]
+    fn collapses_repeated_frames() {
+        let mut frames = vec![frame("inner", 2)];
+        frames.extend((0..4).map(|_| frame("recurse", 5)));
+        frames.extend([
+            frame("recurse", 7),
+            frame("recurse", 7),
+            frame("<script>", 9),
+        ]);
+        assert_eq!(
+            StackTrace(frames).lines("test.lox"),
+            [
+                "at inner (test.lox:2)",
+                "at recurse (test.lox:5)",
+                "... 3 more frames like this",
+                "at recurse (test.lox:7)",
+                "... 1 more frame like this",
+                "at <script> (test.lox:9)",
+            ]
+        );
+    }
+}
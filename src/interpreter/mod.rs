@@ -1,8 +1,54 @@
+mod environment;
+mod error;
 mod tree_walker;
 mod value;
 
-use tree_walker::Interpreter;
+use crate::gc;
+use crate::parser::ast::Stmt;
+use crate::resolver::Locals;
+use crate::vm::{self, Vm};
+pub use error::{RuntimeError, StackFrame, StackTrace};
 
-pub fn new() -> Interpreter {
-    Interpreter::new()
+// the deepest the calls can nest, the script included,
+// before the interpreters give up with a stack overflow
+pub const FRAMES_MAX: usize = 4096;
+
+// The ways a program can be executed
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Backend {
+    // walk the syntax tree
+    Tree,
+    // compile to bytecode and run it on the VM
+    Vm(vm::Options),
+}
+
+// Runs the programs with the backend chosen at startup.
+// Both backends print the same output and raise the same errors
+pub enum Interpreter {
+    Tree(tree_walker::Interpreter),
+    Vm(Vm),
+}
+
+impl Interpreter {
+    // the VM resolves the variables while compiling,
+    // so it doesn't need the resolver's scope depths
+    pub fn resolve(&self, locals: Locals) {
+        if let Interpreter::Tree(interpreter) = self {
+            interpreter.resolve(locals);
+        }
+    }
+
+    pub fn interpret(&mut self, statements: &[Stmt]) -> Result<(), RuntimeError> {
+        match self {
+            Interpreter::Tree(interpreter) => interpreter.interpret(statements),
+            Interpreter::Vm(vm) => vm.interpret(statements),
+        }
+    }
+}
+
+pub fn new(backend: Backend, gc: gc::Options) -> Interpreter {
+    match backend {
+        Backend::Tree => Interpreter::Tree(tree_walker::Interpreter::new(gc)),
+        Backend::Vm(options) => Interpreter::Vm(Vm::new(options, gc)),
+    }
 }
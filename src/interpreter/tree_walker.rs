@@ -1,123 +1,655 @@
-use std::str::FromStr;
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
 
-use super::value::LiteralValue;
+use super::environment::Environment;
+use super::error::{RuntimeError, StackFrame, StackTrace};
+use super::value::{Callable, Class, Function, Instance, LiteralValue};
+use super::FRAMES_MAX;
+use crate::gc::{self, Heap};
+use crate::interner::Symbol;
+use crate::natives::{Native, NativeValue};
 use crate::parser::ast::*;
 use crate::parser::visitor::Visitor;
+use crate::resolver::Locals;
 use crate::token::{Token, TokenType};
 
-pub struct RuntimeError {
-    pub message: String,
-    pub token: Token,
+// A function call in progress and the token of the call site
+struct CallFrame {
+    function: Symbol,
+    call_site: Token,
+}
+
+// The execution of statements can be interrupted either by
+// a runtime error or by a return statement that has to unwind
+// all the statements up to the enclosing function call
+pub enum Unwind {
+    Error(RuntimeError),
+    Return(LiteralValue),
+}
+
+impl From<RuntimeError> for Unwind {
+    fn from(error: RuntimeError) -> Self {
+        Unwind::Error(error)
+    }
+}
+
+struct TreeWalker {
+    // the outermost global environment
+    globals: Rc<RefCell<Environment>>,
+    // the environment of the innermost scope being executed
+    environment: RefCell<Rc<RefCell<Environment>>>,
+    // the scope depth of the local variables found by the resolver
+    locals: RefCell<Locals>,
+    // the user functions being called, from the outermost one
+    call_stack: RefCell<Vec<CallFrame>>,
+    heap: Rc<Heap>,
+    // the environments of the enclosing blocks and of the callers,
+    // restored when the innermost block ends
+    suspended: RefCell<Vec<Rc<RefCell<Environment>>>>,
+    // the values computed while an expression is being evaluated,
+    // that are not stored in any environment yet
+    temporaries: RefCell<Vec<LiteralValue>>,
+}
+
+impl TreeWalker {
+    // execute the statements in the given environment and
+    // restore the previous one afterwards, even on error
+    fn execute_block(&self, statements: &[Stmt], environment: Environment) -> Result<(), Unwind> {
+        let environment = self.heap.allocate(RefCell::new(environment));
+        let previous = self.environment.replace(environment);
+        self.suspended.borrow_mut().push(previous);
+        self.collect_garbage();
+        let result = statements
+            .iter()
+            .try_for_each(|stmt| self.visit_statement(stmt));
+        let previous = self.suspended.borrow_mut().pop().unwrap();
+        self.environment.replace(previous);
+        result
+    }
+
+    // evaluate the expression while the values are kept as temporaries
+    fn holding<T>(&self, values: &[&LiteralValue], evaluate: impl FnOnce() -> T) -> T {
+        let held = self.temporaries.borrow().len();
+        self.temporaries
+            .borrow_mut()
+            .extend(values.iter().map(|value| (*value).clone()));
+        let result = evaluate();
+        self.temporaries.borrow_mut().truncate(held);
+        result
+    }
+
+    // the collections only run where all the values in use are
+    // reachable from the environments or held as temporaries
+    fn collect_garbage(&self) {
+        if !self.heap.collection_due() {
+            return;
+        }
+        self.heap.collect(|tracer| {
+            tracer.mark(&self.globals);
+            tracer.mark(&self.environment.borrow());
+            for environment in self.suspended.borrow().iter() {
+                tracer.mark(environment);
+            }
+            for value in self.temporaries.borrow().iter() {
+                value.trace(tracer);
+            }
+        });
+    }
+
+    // local variables are looked up exactly in the environment
+    // where the resolver found them, the others are globals
+    fn look_up_variable(
+        &self,
+        id: ExpressionId,
+        name: &Token,
+    ) -> Result<LiteralValue, RuntimeError> {
+        match self.locals.borrow().get(&id) {
+            Some(distance) => self.environment.borrow().borrow().get_at(*distance, name),
+            None => self.globals.borrow().get(name),
+        }
+    }
+
+    fn call(
+        &self,
+        callable: &Callable,
+        arguments: Vec<LiteralValue>,
+        call_site: &Token,
+    ) -> Result<LiteralValue, RuntimeError> {
+        match callable {
+            Callable::Native(native) => Ok(self.native_value(native.call(&self.heap))),
+            Callable::Function(function) => self.call_function(function, arguments, call_site),
+        }
+    }
+
+    // convert the result of a native function to a lox value
+    fn native_value(&self, value: NativeValue) -> LiteralValue {
+        match value {
+            NativeValue::Number(number) => LiteralValue::Number(number),
+            NativeValue::Instance { class, fields } => {
+                let class = Rc::new(Class {
+                    name: Symbol::intern(class),
+                    superclass: None,
+                    methods: HashMap::new(),
+                });
+                let mut instance = Instance::new(class);
+                for (name, value) in fields {
+                    instance.set_field(name, LiteralValue::Number(value));
+                }
+                LiteralValue::Instance(self.heap.allocate(RefCell::new(instance)))
+            }
+        }
+    }
+
+    // run the function with a frame on the call stack, so that
+    // an error raised inside it can report where it happened
+    fn call_function(
+        &self,
+        function: &Function,
+        arguments: Vec<LiteralValue>,
+        call_site: &Token,
+    ) -> Result<LiteralValue, RuntimeError> {
+        // the top-level script takes a frame too
+        if self.call_stack.borrow().len() + 1 == FRAMES_MAX {
+            return Err(RuntimeError::new("stack overflow".to_string(), call_site));
+        }
+        self.call_stack.borrow_mut().push(CallFrame {
+            function: function.declaration.name.name(),
+            call_site: call_site.clone(),
+        });
+        let mut result = self.execute_function(function, arguments);
+        if let Err(error) = &mut result {
+            // the trace is captured by the innermost frame
+            // only, before the stack starts unwinding
+            if error.trace.is_none() {
+                error.trace = Some(Box::new(self.stack_trace(error.location.line)));
+            }
+        }
+        self.call_stack.borrow_mut().pop();
+        result
+    }
+
+    // the frames of the call stack from the innermost one,
+    // each with the line it was executing, and the top-level script
+    fn stack_trace(&self, error_line: u64) -> StackTrace {
+        let call_stack = self.call_stack.borrow();
+        let mut trace = Vec::new();
+        let mut line = error_line;
+        for frame in call_stack.iter().rev() {
+            trace.push(StackFrame {
+                function: frame.function.to_string(),
+                line,
+            });
+            line = frame.call_site.line;
+        }
+        trace.push(StackFrame {
+            function: "<script>".to_string(),
+            line,
+        });
+        StackTrace(trace)
+    }
+
+    fn execute_function(
+        &self,
+        function: &Function,
+        arguments: Vec<LiteralValue>,
+    ) -> Result<LiteralValue, RuntimeError> {
+        // each call gets its own environment where the parameters
+        // are bound to the arguments. It encloses the environment
+        // where the function was declared, not the caller's one
+        let mut environment = Environment::new_enclosed(Rc::clone(&function.closure));
+        for (param, argument) in function.declaration.params.iter().zip(arguments) {
+            environment.define(param.name(), argument);
+        }
+        let value = match self.execute_block(&function.declaration.body, environment) {
+            Ok(()) => LiteralValue::Nil,
+            Err(Unwind::Return(value)) => value,
+            Err(Unwind::Error(error)) => return Err(error),
+        };
+
+        // an initializer returns the instance bound to "this",
+        // even when it's invoked directly or with an early return
+        if function.is_initializer {
+            return Ok(function
+                .closure
+                .borrow()
+                .get_local(Symbol::THIS)
+                .unwrap_or(LiteralValue::Nil));
+        }
+        Ok(value)
+    }
+
+    fn evaluate_call(
+        &self,
+        expr: &CallExpression,
+        callee: LiteralValue,
+    ) -> Result<LiteralValue, RuntimeError> {
+        let mut arguments = Vec::new();
+        for argument in &expr.arguments {
+            let argument = self.visit_expression(argument)?;
+            self.temporaries.borrow_mut().push(argument.clone());
+            arguments.push(argument);
+        }
+
+        let arity = match &callee {
+            LiteralValue::Callable(callable) => callable.arity(),
+            LiteralValue::Class(class) => class.arity(),
+            _ => {
+                return Err(RuntimeError::new(
+                    "can only call functions and classes".to_string(),
+                    &expr.paren,
+                ))
+            }
+        };
+        if arguments.len() != arity {
+            return Err(RuntimeError::new(
+                format!("expected {} arguments but got {}", arity, arguments.len()),
+                &expr.paren,
+            ));
+        }
+
+        match &callee {
+            LiteralValue::Class(class) => self.instantiate(class, arguments, &expr.paren),
+            LiteralValue::Callable(callable) => self.call(callable, arguments, &expr.paren),
+            _ => unreachable!("the callee has already been checked to be callable"),
+        }
+    }
+
+    // calling a class creates a new instance and runs its initializer
+    fn instantiate(
+        &self,
+        class: &Rc<Class>,
+        arguments: Vec<LiteralValue>,
+        call_site: &Token,
+    ) -> Result<LiteralValue, RuntimeError> {
+        let instance = self
+            .heap
+            .allocate(RefCell::new(Instance::new(Rc::clone(class))));
+        if let Some(initializer) = class.find_method(Symbol::INIT) {
+            self.call_function(
+                &initializer.bind(Rc::clone(&instance), &self.heap),
+                arguments,
+                call_site,
+            )?;
+        }
+        Ok(LiteralValue::Instance(instance))
+    }
 }
-struct TreeWalker;
 
 pub struct Interpreter {
     tree_walker: TreeWalker,
 }
 
 impl Interpreter {
-    pub fn new() -> Self {
+    pub fn new(options: gc::Options) -> Self {
+        let heap = Rc::new(Heap::new(options));
+        let globals = heap.allocate(RefCell::new(Environment::new()));
+        for native in Native::ALL {
+            globals.borrow_mut().define(
+                Symbol::intern(native.name()),
+                LiteralValue::Callable(Callable::Native(native)),
+            );
+        }
+
         Interpreter {
-            tree_walker: TreeWalker,
+            tree_walker: TreeWalker {
+                globals: Rc::clone(&globals),
+                environment: RefCell::new(globals),
+                locals: RefCell::new(HashMap::new()),
+                call_stack: RefCell::new(Vec::new()),
+                heap,
+                suspended: RefCell::new(Vec::new()),
+                temporaries: RefCell::new(Vec::new()),
+            },
         }
     }
-    pub fn interpret(&self, expr: &Expression) -> Result<LiteralValue, RuntimeError> {
-        self.tree_walker.visit_expression(expr)
+
+    // store the scope depths computed by the resolver.
+    // They are kept across calls so that the functions declared
+    // in previous REPL lines can still be executed
+    pub fn resolve(&self, locals: Locals) {
+        self.tree_walker.locals.borrow_mut().extend(locals);
+    }
+
+    pub fn interpret(&self, statements: &[Stmt]) -> Result<(), RuntimeError> {
+        for stmt in statements {
+            self.tree_walker.collect_garbage();
+            match self.tree_walker.visit_statement(stmt) {
+                Ok(()) => {}
+                Err(Unwind::Error(error)) => return Err(error),
+                // a return outside of any function just stops the script
+                Err(Unwind::Return(_)) => return Ok(()),
+            }
+        }
+        Ok(())
     }
 }
 
-impl Visitor<Result<LiteralValue, RuntimeError>> for TreeWalker {
+impl Visitor<Result<LiteralValue, RuntimeError>, Result<(), Unwind>> for TreeWalker {
+    fn visit_block_statement(&self, stmt: &BlockStatement) -> Result<(), Unwind> {
+        let environment = Environment::new_enclosed(Rc::clone(&self.environment.borrow()));
+        self.execute_block(&stmt.0, environment)
+    }
+
+    fn visit_class_statement(&self, stmt: &ClassStatement) -> Result<(), Unwind> {
+        let superclass = match &stmt.superclass {
+            Some(superclass) => match self.visit_variable_expression(superclass)? {
+                LiteralValue::Class(class) => Some(class),
+                _ => {
+                    return Err(Unwind::Error(RuntimeError::new(
+                        "superclass must be a class".to_string(),
+                        &superclass.name,
+                    )))
+                }
+            },
+            None => None,
+        };
+
+        // the methods of a subclass close over an
+        // environment where "super" is bound to the superclass
+        let mut closure = Rc::clone(&self.environment.borrow());
+        if let Some(superclass) = &superclass {
+            let mut environment = Environment::new_enclosed(closure);
+            environment.define(Symbol::SUPER, LiteralValue::Class(Rc::clone(superclass)));
+            closure = self.heap.allocate(RefCell::new(environment));
+        }
+
+        let mut methods = HashMap::new();
+        for method in &stmt.methods {
+            let function = Function {
+                declaration: Rc::clone(method),
+                closure: Rc::clone(&closure),
+                is_initializer: method.name.name() == Symbol::INIT,
+            };
+            methods.insert(method.name.name(), Rc::new(function));
+        }
+
+        let class = Class {
+            name: stmt.name.name(),
+            superclass,
+            methods,
+        };
+        self.environment
+            .borrow()
+            .borrow_mut()
+            .define(stmt.name.name(), LiteralValue::Class(Rc::new(class)));
+        Ok(())
+    }
+
+    fn visit_expression_statement(&self, stmt: &ExpressionStatement) -> Result<(), Unwind> {
+        self.visit_expression(&stmt.0)?;
+        Ok(())
+    }
+
+    fn visit_function_statement(&self, stmt: &Rc<FunctionStatement>) -> Result<(), Unwind> {
+        let function = Function {
+            declaration: Rc::clone(stmt),
+            closure: Rc::clone(&self.environment.borrow()),
+            is_initializer: false,
+        };
+        self.environment.borrow().borrow_mut().define(
+            stmt.name.name(),
+            LiteralValue::Callable(Callable::Function(Rc::new(function))),
+        );
+        Ok(())
+    }
+
+    fn visit_if_statement(&self, stmt: &IfStatement) -> Result<(), Unwind> {
+        if self.visit_expression(&stmt.condition)?.is_truthy() {
+            self.visit_statement(&stmt.then_branch)
+        } else if let Some(else_branch) = &stmt.else_branch {
+            self.visit_statement(else_branch)
+        } else {
+            Ok(())
+        }
+    }
+
+    fn visit_print_statement(&self, stmt: &PrintStatement) -> Result<(), Unwind> {
+        let value = self.visit_expression(&stmt.0)?;
+        println!("{}", value);
+        Ok(())
+    }
+
+    fn visit_return_statement(&self, stmt: &ReturnStatement) -> Result<(), Unwind> {
+        let value = match &stmt.value {
+            Some(value) => self.visit_expression(value)?,
+            None => LiteralValue::Nil,
+        };
+        Err(Unwind::Return(value))
+    }
+
+    fn visit_var_statement(&self, stmt: &VarStatement) -> Result<(), Unwind> {
+        // a variable declared without an initializer is nil
+        let value = match &stmt.initializer {
+            Some(initializer) => self.visit_expression(initializer)?,
+            None => LiteralValue::Nil,
+        };
+        self.environment
+            .borrow()
+            .borrow_mut()
+            .define(stmt.name.name(), value);
+        Ok(())
+    }
+
+    fn visit_while_statement(&self, stmt: &WhileStatement) -> Result<(), Unwind> {
+        while self.visit_expression(&stmt.condition)?.is_truthy() {
+            self.visit_statement(&stmt.body)?;
+            self.collect_garbage();
+        }
+        Ok(())
+    }
+
+    fn visit_assign_expression(
+        &self,
+        expr: &AssignExpression,
+    ) -> Result<LiteralValue, RuntimeError> {
+        let value = self.visit_expression(&expr.value)?;
+        match self.locals.borrow().get(&expr.id) {
+            Some(distance) => self.environment.borrow().borrow_mut().assign_at(
+                *distance,
+                &expr.name,
+                value.clone(),
+            )?,
+            None => self
+                .globals
+                .borrow_mut()
+                .assign(&expr.name, value.clone())?,
+        }
+        // assignment is an expression that evaluates to the assigned value
+        Ok(value)
+    }
+
     fn visit_binary_expression(
         &self,
         expr: &BinaryExpression,
     ) -> Result<LiteralValue, RuntimeError> {
         let left_val = self.visit_expression(&expr.left)?;
-        let right_val = self.visit_expression(&expr.right)?;
+        let right_val = self.holding(&[&left_val], || self.visit_expression(&expr.right))?;
         match expr.operator.r#type {
             TokenType::Minus => match (left_val, right_val) {
                 (LiteralValue::Number(left_num), LiteralValue::Number(right_num)) => {
-                    return Ok(LiteralValue::Number(left_num - right_num))
+                    Ok(LiteralValue::Number(left_num - right_num))
                 }
-                _ => Err(RuntimeError {
-                    message: "invalid operand type: a numerical value is expected".to_string(),
-                    token: expr.operator.clone(),
-                }),
+                _ => Err(RuntimeError::new(
+                    "invalid operand type: a numerical value is expected".to_string(),
+                    &expr.operator,
+                )),
             },
             TokenType::Slash => match (left_val, right_val) {
                 (LiteralValue::Number(left_num), LiteralValue::Number(right_num)) => {
-                    return Ok(LiteralValue::Number(left_num / right_num))
+                    Ok(LiteralValue::Number(left_num / right_num))
                 }
-                _ => Err(RuntimeError {
-                    message: "invalid operand type: a numerical value is expected".to_string(),
-                    token: expr.operator.clone(),
-                }),
+                _ => Err(RuntimeError::new(
+                    "invalid operand type: a numerical value is expected".to_string(),
+                    &expr.operator,
+                )),
             },
             TokenType::Star => match (left_val, right_val) {
                 (LiteralValue::Number(left_num), LiteralValue::Number(right_num)) => {
-                    return Ok(LiteralValue::Number(left_num * right_num))
+                    Ok(LiteralValue::Number(left_num * right_num))
                 }
-                _ => Err(RuntimeError {
-                    message: "invalid operand type: a numerical value is expected".to_string(),
-                    token: expr.operator.clone(),
-                }),
+                _ => Err(RuntimeError::new(
+                    "invalid operand type: a numerical value is expected".to_string(),
+                    &expr.operator,
+                )),
             },
             TokenType::Plus => match (left_val, right_val) {
                 (LiteralValue::Number(left_num), LiteralValue::Number(right_num)) => {
-                    return Ok(LiteralValue::Number(left_num + right_num))
-                }
-                (LiteralValue::String(left_str), LiteralValue::String(right_str)) => {
-                    return Ok(LiteralValue::String(left_str + &right_str))
+                    Ok(LiteralValue::Number(left_num + right_num))
                 }
-                _ => Err(RuntimeError {
-                    message: "operands must be two numbers or two strings.".to_string(),
-                    token: expr.operator.clone(),
-                }),
+                (LiteralValue::String(left_str), LiteralValue::String(right_str)) => Ok(
+                    LiteralValue::String(Rc::from([left_str, right_str].concat())),
+                ),
+                _ => Err(RuntimeError::new(
+                    "operands must be two numbers or two strings.".to_string(),
+                    &expr.operator,
+                )),
             },
             TokenType::Greater => match (left_val, right_val) {
                 (LiteralValue::Number(left_num), LiteralValue::Number(right_num)) => {
-                    return Ok(LiteralValue::Bool(left_num > right_num))
+                    Ok(LiteralValue::Bool(left_num > right_num))
                 }
-                _ => Err(RuntimeError {
-                    message: "invalid operand type: a numerical value is expected".to_string(),
-                    token: expr.operator.clone(),
-                }),
+                _ => Err(RuntimeError::new(
+                    "invalid operand type: a numerical value is expected".to_string(),
+                    &expr.operator,
+                )),
             },
             TokenType::GreaterEqual => match (left_val, right_val) {
                 (LiteralValue::Number(left_num), LiteralValue::Number(right_num)) => {
-                    return Ok(LiteralValue::Bool(left_num >= right_num))
+                    Ok(LiteralValue::Bool(left_num >= right_num))
                 }
-                _ => Err(RuntimeError {
-                    message: "invalid operand type: a numerical value is expected".to_string(),
-                    token: expr.operator.clone(),
-                }),
+                _ => Err(RuntimeError::new(
+                    "invalid operand type: a numerical value is expected".to_string(),
+                    &expr.operator,
+                )),
             },
             TokenType::Less => match (left_val, right_val) {
                 (LiteralValue::Number(left_num), LiteralValue::Number(right_num)) => {
-                    return Ok(LiteralValue::Bool(left_num < right_num))
+                    Ok(LiteralValue::Bool(left_num < right_num))
                 }
-                _ => Err(RuntimeError {
-                    message: "invalid operand type: a numerical value is expected".to_string(),
-                    token: expr.operator.clone(),
-                }),
+                _ => Err(RuntimeError::new(
+                    "invalid operand type: a numerical value is expected".to_string(),
+                    &expr.operator,
+                )),
             },
             TokenType::LessEqual => match (left_val, right_val) {
                 (LiteralValue::Number(left_num), LiteralValue::Number(right_num)) => {
-                    return Ok(LiteralValue::Bool(left_num <= right_num))
+                    Ok(LiteralValue::Bool(left_num <= right_num))
                 }
-                _ => Err(RuntimeError {
-                    message: "invalid operand type: a numerical value is expected".to_string(),
-                    token: expr.operator.clone(),
-                }),
+                _ => Err(RuntimeError::new(
+                    "invalid operand type: a numerical value is expected".to_string(),
+                    &expr.operator,
+                )),
             },
-            TokenType::EqualEqual => return Ok(LiteralValue::Bool(left_val == right_val)),
-            TokenType::BangEqual => return Ok(LiteralValue::Bool(!(left_val == right_val))),
-            _ => Err(RuntimeError {
-                message: "operator not supported".to_string(),
-                token: expr.operator.clone(),
-            }),
+            TokenType::EqualEqual => Ok(LiteralValue::Bool(left_val == right_val)),
+            TokenType::BangEqual => Ok(LiteralValue::Bool(!(left_val == right_val))),
+            _ => Err(RuntimeError::new(
+                "operator not supported".to_string(),
+                &expr.operator,
+            )),
+        }
+    }
+
+    fn visit_call_expression(&self, expr: &CallExpression) -> Result<LiteralValue, RuntimeError> {
+        let callee = self.visit_expression(&expr.callee)?;
+        // the callee and the arguments are held until the call returns
+        let held = self.temporaries.borrow().len();
+        self.temporaries.borrow_mut().push(callee.clone());
+        let result = self.evaluate_call(expr, callee);
+        self.temporaries.borrow_mut().truncate(held);
+        result
+    }
+
+    fn visit_comma_expression(&self, expr: &CommaExpression) -> Result<LiteralValue, RuntimeError> {
+        // the left operand is evaluated only for its side effects
+        self.visit_expression(&expr.left)?;
+        self.visit_expression(&expr.right)
+    }
+
+    // only the chosen branch is evaluated
+    fn visit_conditional_expression(
+        &self,
+        expr: &ConditionalExpression,
+    ) -> Result<LiteralValue, RuntimeError> {
+        if self.visit_expression(&expr.condition)?.is_truthy() {
+            self.visit_expression(&expr.then_branch)
+        } else {
+            self.visit_expression(&expr.else_branch)
+        }
+    }
+
+    fn visit_get_expression(&self, expr: &GetExpression) -> Result<LiteralValue, RuntimeError> {
+        match self.visit_expression(&expr.object)? {
+            LiteralValue::Instance(instance) => Instance::get(&instance, &expr.name, &self.heap),
+            _ => Err(RuntimeError::new(
+                format!(
+                    "only instances have properties, can't read '{}'",
+                    expr.name.name()
+                ),
+                &expr.name,
+            )),
         }
     }
 
+    fn visit_set_expression(&self, expr: &SetExpression) -> Result<LiteralValue, RuntimeError> {
+        let object = self.visit_expression(&expr.object)?;
+        let LiteralValue::Instance(instance) = &object else {
+            return Err(RuntimeError::new(
+                format!(
+                    "only instances have fields, can't set '{}'",
+                    expr.name.name()
+                ),
+                &expr.name,
+            ));
+        };
+        let value = self.holding(&[&object], || self.visit_expression(&expr.value))?;
+        instance.borrow_mut().set(&expr.name, value.clone());
+        Ok(value)
+    }
+
+    // "super" is bound in the environment enclosing the one
+    // with "this", so the instance is always one hop closer
+    fn visit_super_expression(&self, expr: &SuperExpression) -> Result<LiteralValue, RuntimeError> {
+        let distance = match self.locals.borrow().get(&expr.id) {
+            Some(distance) => *distance,
+            None => {
+                return Err(RuntimeError::new(
+                    "can't use 'super' outside of a class".to_string(),
+                    &expr.keyword,
+                ))
+            }
+        };
+        let environment = self.environment.borrow();
+        let superclass = Environment::ancestor(&environment, distance)
+            .borrow()
+            .get_local(Symbol::SUPER);
+        let instance = Environment::ancestor(&environment, distance - 1)
+            .borrow()
+            .get_local(Symbol::THIS);
+
+        match (superclass, instance) {
+            (Some(LiteralValue::Class(superclass)), Some(LiteralValue::Instance(instance))) => {
+                match superclass.find_method(expr.method.name()) {
+                    Some(method) => Ok(LiteralValue::Callable(Callable::Function(Rc::new(
+                        method.bind(instance, &self.heap),
+                    )))),
+                    None => Err(RuntimeError::new(
+                        format!("undefined property '{}'", expr.method.name()),
+                        &expr.method,
+                    )),
+                }
+            }
+            _ => Err(RuntimeError::new(
+                "can't use 'super' outside of a class".to_string(),
+                &expr.keyword,
+            )),
+        }
+    }
+
+    fn visit_this_expression(&self, expr: &ThisExpression) -> Result<LiteralValue, RuntimeError> {
+        self.look_up_variable(expr.id, &expr.keyword)
+    }
+
     fn visit_grouping_expression(
         &self,
         expr: &GroupingExpression,
@@ -131,15 +663,39 @@ impl Visitor<Result<LiteralValue, RuntimeError>> for TreeWalker {
     ) -> Result<LiteralValue, RuntimeError> {
         match expr {
             LiteralExpression::Nil(_) => Ok(LiteralValue::Nil),
-            LiteralExpression::Bool(token) => Ok(LiteralValue::Bool(
-                FromStr::from_str(&token.lexeme).unwrap(),
-            )),
-            LiteralExpression::String(token) => Ok(LiteralValue::String(
-                token.lexeme.trim_matches('"').to_string(),
-            )),
-            LiteralExpression::Number(token) => {
-                Ok(LiteralValue::Number(f64::from_str(&token.lexeme).unwrap()))
+            LiteralExpression::Bool(token) => {
+                Ok(LiteralValue::Bool(token.r#type == TokenType::True))
             }
+            // the scanner has already unescaped the content of the string
+            LiteralExpression::String(token) => match &token.r#type {
+                TokenType::String(value) => Ok(LiteralValue::String(value.string())),
+                _ => unreachable!("string literals are built from string tokens"),
+            },
+            // the lexeme can have a prefix or separators, e.g. 0xff_ff,
+            // so the value computed by the scanner is used instead
+            LiteralExpression::Number(token) => match token.r#type {
+                TokenType::Number(value) => Ok(LiteralValue::Number(value)),
+                _ => unreachable!("number literals are built from number tokens"),
+            },
+        }
+    }
+
+    // the logical operators short-circuit and, instead of
+    // a boolean, return the value of the operand that decided
+    // the result, e.g. `nil or "default"` returns "default"
+    fn visit_logical_expression(
+        &self,
+        expr: &LogicalExpression,
+    ) -> Result<LiteralValue, RuntimeError> {
+        let left_val = self.visit_expression(&expr.left)?;
+        match expr.operator.r#type {
+            TokenType::Or if left_val.is_truthy() => Ok(left_val),
+            TokenType::And if !left_val.is_truthy() => Ok(left_val),
+            TokenType::Or | TokenType::And => self.visit_expression(&expr.right),
+            _ => Err(RuntimeError::new(
+                "operator not supported".to_string(),
+                &expr.operator,
+            )),
         }
     }
 
@@ -149,19 +705,26 @@ impl Visitor<Result<LiteralValue, RuntimeError>> for TreeWalker {
                 if let LiteralValue::Number(number) = self.visit_expression(&expr.operand)? {
                     Ok(LiteralValue::Number(-number))
                 } else {
-                    Err(RuntimeError {
-                        message: "invalid operand type: a numerical value is expected".to_string(),
-                        token: expr.operator.clone(),
-                    })
+                    Err(RuntimeError::new(
+                        "invalid operand type: a numerical value is expected".to_string(),
+                        &expr.operator,
+                    ))
                 }
             }
             TokenType::Bang => Ok(LiteralValue::Bool(
                 !self.visit_expression(&expr.operand)?.is_truthy(),
             )),
-            _ => Err(RuntimeError {
-                message: "invalid operand type: a numerical value is expected".to_string(),
-                token: expr.operator.clone(),
-            }),
+            _ => Err(RuntimeError::new(
+                "invalid operand type: a numerical value is expected".to_string(),
+                &expr.operator,
+            )),
         }
     }
+
+    fn visit_variable_expression(
+        &self,
+        expr: &VariableExpression,
+    ) -> Result<LiteralValue, RuntimeError> {
+        self.look_up_variable(expr.id, &expr.name)
+    }
 }
@@ -1,11 +1,203 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
 use std::fmt;
+use std::rc::Rc;
 
-#[derive(PartialEq)]
+use super::environment::Environment;
+use super::error::RuntimeError;
+use crate::gc::{Heap, Trace, Tracer};
+use crate::interner::Symbol;
+use crate::natives::Native;
+use crate::parser::ast::FunctionStatement;
+use crate::token::Token;
+
+#[derive(Clone)]
 pub enum LiteralValue {
     Nil,
     Bool(bool),
-    String(String),
+    // the strings of the literals are shared with the symbol table
+    String(Rc<str>),
     Number(f64),
+    Callable(Callable),
+    Class(Rc<Class>),
+    Instance(Rc<RefCell<Instance>>),
+}
+
+// Anything that can be invoked with a call expression
+#[derive(Clone)]
+pub enum Callable {
+    Function(Rc<Function>),
+    Native(Native),
+}
+
+// A function declared in the lox code
+pub struct Function {
+    pub declaration: Rc<FunctionStatement>,
+    // the environment active when the function was declared.
+    // It is shared with the scope that declared the function
+    // and outlives it for as long as the function is alive
+    pub closure: Rc<RefCell<Environment>>,
+    // initializers always return the instance they are called on
+    pub is_initializer: bool,
+}
+
+impl Function {
+    // create a copy of the method whose closure
+    // binds "this" to the given instance
+    pub fn bind(&self, instance: Rc<RefCell<Instance>>, heap: &Heap) -> Function {
+        let mut environment = Environment::new_enclosed(Rc::clone(&self.closure));
+        environment.define(Symbol::THIS, LiteralValue::Instance(instance));
+        Function {
+            declaration: Rc::clone(&self.declaration),
+            closure: heap.allocate(RefCell::new(environment)),
+            is_initializer: self.is_initializer,
+        }
+    }
+}
+
+pub struct Class {
+    pub name: Symbol,
+    pub superclass: Option<Rc<Class>>,
+    pub methods: HashMap<Symbol, Rc<Function>>,
+}
+
+impl Class {
+    // methods not found in the class are looked up the inheritance chain
+    pub fn find_method(&self, name: Symbol) -> Option<Rc<Function>> {
+        match self.methods.get(&name) {
+            Some(method) => Some(Rc::clone(method)),
+            None => self
+                .superclass
+                .as_ref()
+                .and_then(|superclass| superclass.find_method(name)),
+        }
+    }
+
+    // calling a class runs its initializer, if any,
+    // so the class takes the same arguments as "init"
+    pub fn arity(&self) -> usize {
+        match self.find_method(Symbol::INIT) {
+            Some(initializer) => initializer.declaration.params.len(),
+            None => 0,
+        }
+    }
+
+    // classes are not tracked, they can't be part of a cycle
+    // without one of the environments their methods close over
+    fn trace(&self, tracer: &mut Tracer) {
+        for method in self.methods.values() {
+            tracer.mark(&method.closure);
+        }
+        if let Some(superclass) = &self.superclass {
+            superclass.trace(tracer);
+        }
+    }
+}
+
+pub struct Instance {
+    pub class: Rc<Class>,
+    fields: HashMap<Symbol, LiteralValue>,
+}
+
+impl Instance {
+    pub fn new(class: Rc<Class>) -> Self {
+        Instance {
+            class,
+            fields: HashMap::new(),
+        }
+    }
+
+    // fields shadow the methods with the same name.
+    // Methods are bound to the instance they are accessed from
+    pub fn get(
+        instance: &Rc<RefCell<Instance>>,
+        name: &Token,
+        heap: &Heap,
+    ) -> Result<LiteralValue, RuntimeError> {
+        if let Some(value) = instance.borrow().fields.get(&name.name()) {
+            return Ok(value.clone());
+        }
+        match instance.borrow().class.find_method(name.name()) {
+            Some(method) => Ok(LiteralValue::Callable(Callable::Function(Rc::new(
+                method.bind(Rc::clone(instance), heap),
+            )))),
+            None => Err(RuntimeError::new(
+                format!("undefined property '{}'", name.name()),
+                name,
+            )),
+        }
+    }
+
+    pub fn set(&mut self, name: &Token, value: LiteralValue) {
+        self.fields.insert(name.name(), value);
+    }
+
+    pub fn set_field(&mut self, name: &str, value: LiteralValue) {
+        self.fields.insert(Symbol::intern(name), value);
+    }
+}
+
+impl Trace for RefCell<Instance> {
+    fn trace(&self, tracer: &mut Tracer) {
+        let instance = self.borrow();
+        instance.class.trace(tracer);
+        for value in instance.fields.values() {
+            value.trace(tracer);
+        }
+    }
+
+    fn clear(&self) {
+        self.borrow_mut().fields.clear();
+    }
+}
+
+impl Callable {
+    // the number of arguments the callable expects
+    pub fn arity(&self) -> usize {
+        match self {
+            Callable::Function(function) => function.declaration.params.len(),
+            Callable::Native(native) => native.arity(),
+        }
+    }
+}
+
+// two callables are equal only if they are the very same object
+impl PartialEq for Callable {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Callable::Function(left), Callable::Function(right)) => Rc::ptr_eq(left, right),
+            (Callable::Native(left), Callable::Native(right)) => left == right,
+            _ => false,
+        }
+    }
+}
+
+impl fmt::Display for Callable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Callable::Function(function) => write!(f, "<fn {}>", function.declaration.name.name()),
+            Callable::Native(native) => write!(f, "<native fn {}>", native.name()),
+        }
+    }
+}
+
+// numbers, strings and booleans are compared by value,
+// all the other objects by identity
+impl PartialEq for LiteralValue {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (LiteralValue::Nil, LiteralValue::Nil) => true,
+            (LiteralValue::Bool(left), LiteralValue::Bool(right)) => left == right,
+            (LiteralValue::String(left), LiteralValue::String(right)) => left == right,
+            (LiteralValue::Number(left), LiteralValue::Number(right)) => left == right,
+            (LiteralValue::Callable(left), LiteralValue::Callable(right)) => left == right,
+            (LiteralValue::Class(left), LiteralValue::Class(right)) => Rc::ptr_eq(left, right),
+            (LiteralValue::Instance(left), LiteralValue::Instance(right)) => {
+                Rc::ptr_eq(left, right)
+            }
+            _ => false,
+        }
+    }
 }
 
 impl LiteralValue {
@@ -28,6 +220,16 @@ impl LiteralValue {
             _ => true,
         }
     }
+
+    // mark the tracked objects the value references
+    pub fn trace(&self, tracer: &mut Tracer) {
+        match self {
+            LiteralValue::Callable(Callable::Function(function)) => tracer.mark(&function.closure),
+            LiteralValue::Class(class) => class.trace(tracer),
+            LiteralValue::Instance(instance) => tracer.mark(instance),
+            _ => {}
+        }
+    }
 }
 
 impl fmt::Display for LiteralValue {
@@ -37,6 +239,11 @@ impl fmt::Display for LiteralValue {
             LiteralValue::Bool(bool) => write!(f, "{}", bool),
             LiteralValue::String(string) => write!(f, "{}", string),
             LiteralValue::Number(number) => write!(f, "{}", number),
+            LiteralValue::Callable(callable) => write!(f, "{}", callable),
+            LiteralValue::Class(class) => write!(f, "{}", class.name),
+            LiteralValue::Instance(instance) => {
+                write!(f, "{} instance", instance.borrow().class.name)
+            }
         }
     }
 }
@@ -1,37 +1,150 @@
+pub mod diagnostics;
+pub mod gc;
+pub mod interner;
 pub mod interpreter;
+pub mod natives;
+pub mod optimizer;
 pub mod parser;
+pub mod resolver;
 pub mod scanner;
 pub mod token;
+pub mod vm;
 
-use std::io::{self, Write};
+use std::io::{self, IsTerminal, Write};
+use std::thread;
 
-use parser::visitor::GraphGenerator;
-use scanner::Scanner;
+use diagnostics::{Diagnostic, Renderer};
+use interpreter::{Backend, RuntimeError};
+use parser::ast::Stmt;
+use parser::visitor::{GraphGenerator, PrettyPrinter, Visitor};
+use parser::ParseError;
+use resolver::ResolveError;
+use scanner::{LexicalError, Scanner};
+use token::TokenType;
 
+use clap::error::ErrorKind;
+use clap::parser::ValueSource;
 use clap::{arg, Command};
 
+// the stack of the thread running the program. Only the
+// part actually used is allocated
+const STACK_SIZE: usize = 256 * 1024 * 1024;
+
 fn get_args_parser() -> Command {
     Command::new("jlox")
     .version("1.0")
     .about("jlox repl and interpreter")
     .args(&[
         arg!(-s --syntaxtree "generate svg with the syntaxt tree"),
+        arg!(--color "always use colors in the error reports").conflicts_with("no-color"),
+        arg!(--"no-color" "never use colors in the error reports"),
+        arg!(--backend <BACKEND> "how to run the program: walking the syntax tree or compiling it for the bytecode VM")
+            .value_parser(["tree", "vm"])
+            .default_value("tree"),
+        arg!(--disassemble "print the bytecode of the program before running it on the VM"),
+        arg!(--"trace-exec" "print the VM stack and each instruction as it's executed"),
+        arg!(--"no-opt" "run the program as it's written, without optimizing the syntax tree"),
+        arg!(--"dump-ast" "print the syntax tree that is run, after the optimizations"),
+        arg!(--"gc-stress" "run the garbage collector on every allocation"),
+        arg!(--"gc-threshold" <BYTES> "the heap size that triggers the first garbage collection")
+            .value_parser(clap::value_parser!(usize)),
         arg!([script] "an optional script file to interpret. If this is missing a repl will be launched instead"),
     ])
 }
 
+// how the program is processed and run
+#[derive(Clone, Copy)]
+struct Config {
+    syntax_tree: bool,
+    color: bool,
+    optimize: bool,
+    dump_ast: bool,
+    backend: Backend,
+    gc: gc::Options,
+}
+
 fn main() {
     let arg_matches = get_args_parser().get_matches();
     let syntax_tree = arg_matches.get_flag("syntaxtree");
-    match arg_matches.get_one::<String>("script") {
-        Some(script) => run_file(script, syntax_tree),
-        None => run_prompt(syntax_tree),
+    // without an explicit choice, colors are used
+    // only when the errors are shown on a terminal
+    let color = if arg_matches.get_flag("color") {
+        true
+    } else if arg_matches.get_flag("no-color") {
+        false
+    } else {
+        io::stderr().is_terminal()
+    };
+    let options = vm::Options {
+        disassemble: arg_matches.get_flag("disassemble"),
+        trace_execution: arg_matches.get_flag("trace-exec"),
+    };
+    let mut gc = gc::Options {
+        stress: arg_matches.get_flag("gc-stress"),
+        ..gc::Options::default()
+    };
+    if let Some(threshold) = arg_matches.get_one::<usize>("gc-threshold") {
+        gc.threshold = *threshold;
+    }
+    // the bytecode debugging flags imply the VM backend,
+    // but they can't override an explicit choice of the tree walker
+    let backend = match arg_matches.get_one::<String>("backend").map(String::as_str) {
+        Some("vm") => Backend::Vm(options),
+        _ if options == vm::Options::default() => Backend::Tree,
+        _ if arg_matches.value_source("backend") == Some(ValueSource::CommandLine) => {
+            let flag = if options.disassemble {
+                "--disassemble"
+            } else {
+                "--trace-exec"
+            };
+            get_args_parser()
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    format!(
+                        "the argument '{}' runs on the VM and cannot be used with '--backend tree'",
+                        flag
+                    ),
+                )
+                .exit()
+        }
+        _ => Backend::Vm(options),
+    };
+    let config = Config {
+        syntax_tree,
+        color,
+        optimize: !arg_matches.get_flag("no-opt"),
+        dump_ast: arg_matches.get_flag("dump-ast"),
+        backend,
+        gc,
+    };
+    let script = arg_matches.get_one::<String>("script").cloned();
+    // the tree walker nests a few Rust calls for each Lox call, so the
+    // program runs on a thread with a stack that has room for all the frames
+    // the interpreters allow, instead of overflowing the main thread's one
+    let runner = thread::Builder::new()
+        .stack_size(STACK_SIZE)
+        .spawn(move || match script {
+            Some(script) => run_file(&script, config),
+            None => run_prompt(config),
+        })
+        .expect("failed to start the interpreter thread");
+    // the thread already reported the panic, exit
+    // as the panic of the main thread would have
+    if runner.join().is_err() {
+        std::process::exit(101)
     }
 }
 
-fn run_prompt(syntax_tree: bool) {
+fn run_prompt(config: Config) {
     let mut input: String = String::new();
     let stdin = std::io::stdin();
+    // the interpreter is shared by all the lines so that
+    // global variables persist across the whole session
+    let mut interpreter = interpreter::new(config.backend, config.gc);
+    // all the lines entered so far, since the errors can
+    // point to a function declared on a previous line
+    let mut session = String::new();
+    let mut line = 1;
     loop {
         input.clear();
         print!("> ");
@@ -42,21 +155,41 @@ fn run_prompt(syntax_tree: bool) {
                 break;
             }
             Ok(_) => {
-                let mut scanner = Scanner::new(input.as_str());
-                let tokens = scanner.scan_tokens();
+                if !input.ends_with('\n') {
+                    input.push('\n');
+                }
+                let offset = session.len();
+                session.push_str(&input);
+                let mut scanner = Scanner::new(input.as_str()).continuing(offset, line);
+                line += input.matches('\n').count() as u64;
+                let renderer = Renderer::new("<repl>", &session, config.color);
+                let (tokens, lexical_errors) = scanner.scan_tokens();
+                report_lexical_errors(&renderer, &lexical_errors);
 
-                let expr_tree = parser::parse(tokens);
-                if syntax_tree {
+                let statements = match parser::parse(tokens) {
+                    Ok(statements) if lexical_errors.is_empty() => statements,
+                    Ok(_) => continue,
+                    Err(errors) => {
+                        report_parse_errors(&renderer, &errors);
+                        continue;
+                    }
+                };
+                if config.syntax_tree {
                     let graph_generator = GraphGenerator::new();
-                    match graph_generator.generate_tree(&expr_tree) {
-                        Err(error) => println!("Error trying to generate the graph: {}", error),
-                        _ => {}
+                    if let Err(error) = graph_generator.generate_tree(&statements) {
+                        println!("Error trying to generate the graph: {}", error)
+                    }
+                }
+                match resolver::resolve(&statements) {
+                    Ok(locals) => interpreter.resolve(locals),
+                    Err(errors) => {
+                        report_resolve_errors(&renderer, &errors);
+                        continue;
                     }
                 }
-                let interpreter = interpreter::new();
-                match interpreter.interpret(&expr_tree) {
-                    Err(error) => println!("{}\n[line {} ]", error.message, error.token.line),
-                    Ok(value) => println!("{}", value),
+                let statements = prepare(statements, config);
+                if let Err(error) = interpreter.interpret(&statements) {
+                    report_runtime_error(&renderer, &error)
                 }
             }
             Err(err) => println!("Cannot read command line input: {}", err),
@@ -64,31 +197,51 @@ fn run_prompt(syntax_tree: bool) {
     }
 }
 
-fn run_file(path: &String, syntax_tree: bool) {
+fn run_file(path: &String, config: Config) {
     // Open the file in read-only mode.
     match std::fs::read_to_string(path) {
         Ok(script) => {
+            let renderer = Renderer::new(path, &script, config.color);
             let mut scanner = Scanner::new(&script);
-            let tokens = scanner.scan_tokens();
+            let (tokens, lexical_errors) = scanner.scan_tokens();
+            report_lexical_errors(&renderer, &lexical_errors);
 
-            let expr_tree = parser::parse(tokens);
-            if syntax_tree {
+            // the parser runs even after lexical errors,
+            // so that all the errors are reported at once
+            let statements = match parser::parse(tokens) {
+                Ok(statements) if lexical_errors.is_empty() => statements,
+                result => {
+                    if let Err(errors) = result {
+                        report_parse_errors(&renderer, &errors);
+                    }
+                    // see error codes
+                    // https://man.freebsd.org/cgi/man.cgi?query=sysexits&apropos=0&sektion=0&manpath=FreeBSD+4.3-RELEASE&format=html
+                    std::process::exit(65)
+                }
+            };
+            if config.syntax_tree {
                 println!("printing syntax tree");
                 let graph_generator = GraphGenerator::new();
-                match graph_generator.generate_tree(&expr_tree) {
-                    Err(error) => println!("Error trying to generate the graph: {}", error),
-                    _ => {}
+                if let Err(error) = graph_generator.generate_tree(&statements) {
+                    println!("Error trying to generate the graph: {}", error)
                 }
             }
-            let interpreter = interpreter::new();
-            match interpreter.interpret(&expr_tree) {
-                Err(error) => {
-                    println!("{}\n[line {} ]", error.message, error.token.line);
+            let mut interpreter = interpreter::new(config.backend, config.gc);
+            match resolver::resolve(&statements) {
+                Ok(locals) => interpreter.resolve(locals),
+                Err(errors) => {
+                    report_resolve_errors(&renderer, &errors);
                     // see error codes
                     // https://man.freebsd.org/cgi/man.cgi?query=sysexits&apropos=0&sektion=0&manpath=FreeBSD+4.3-RELEASE&format=html
-                    std::process::exit(64)
+                    std::process::exit(65)
                 }
-                Ok(value) => println!("{}", value),
+            }
+            let statements = prepare(statements, config);
+            if let Err(error) = interpreter.interpret(&statements) {
+                report_runtime_error(&renderer, &error);
+                // see error codes
+                // https://man.freebsd.org/cgi/man.cgi?query=sysexits&apropos=0&sektion=0&manpath=FreeBSD+4.3-RELEASE&format=html
+                std::process::exit(64)
             }
         }
         Err(err) => {
@@ -99,3 +252,53 @@ fn run_file(path: &String, syntax_tree: bool) {
         }
     }
 }
+
+// optimize the resolved program and print it if requested
+fn prepare(statements: Vec<Stmt>, config: Config) -> Vec<Stmt> {
+    let statements = if config.optimize {
+        optimizer::optimize(&statements)
+    } else {
+        statements
+    };
+    if config.dump_ast {
+        for stmt in &statements {
+            println!("{}", PrettyPrinter.visit_statement(stmt));
+        }
+    }
+    statements
+}
+
+fn report_lexical_errors(renderer: &Renderer, errors: &[LexicalError]) {
+    for error in errors {
+        renderer.emit(&Diagnostic::new(&error.message, &error.token));
+    }
+}
+
+fn report_parse_errors(renderer: &Renderer, errors: &[ParseError]) {
+    for error in errors {
+        let mut diagnostic = Diagnostic::new(&error.message, &error.token);
+        if error.token.r#type == TokenType::EOF {
+            diagnostic = diagnostic.with_note("reached the end of the input");
+        }
+        renderer.emit(&diagnostic);
+    }
+}
+
+fn report_resolve_errors(renderer: &Renderer, errors: &[ResolveError]) {
+    for error in errors {
+        renderer
+            .emit(&Diagnostic::new(&error.message, &error.token).with_help(error.help.as_deref()));
+    }
+}
+
+fn report_runtime_error(renderer: &Renderer, error: &RuntimeError) {
+    let mut diagnostic = Diagnostic::at(&error.message, error.location);
+    // the traceback goes from the innermost call to the top-level script
+    if let Some(trace) = &error.trace {
+        diagnostic = diagnostic.with_note(&format!(
+            "stack trace:\n{}",
+            trace.lines(renderer.file_name()).join("\n")
+        ));
+    }
+    renderer.emit(&diagnostic);
+}
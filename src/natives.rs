@@ -0,0 +1,62 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::gc::Heap;
+
+// The functions implemented in Rust and exposed to the lox code.
+// They are shared by both backends, so they return plain data
+// that each backend converts to its own values
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Native {
+    // the seconds since the Unix epoch
+    Clock,
+    // the statistics of the garbage collector
+    GcStats,
+}
+
+// The result of a native function
+pub enum NativeValue {
+    Number(f64),
+    // an instance of a class without methods, with the given fields
+    Instance {
+        class: &'static str,
+        fields: Vec<(&'static str, f64)>,
+    },
+}
+
+impl Native {
+    // the natives defined in the global scope
+    pub const ALL: [Native; 2] = [Native::Clock, Native::GcStats];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Native::Clock => "clock",
+            Native::GcStats => "gcStats",
+        }
+    }
+
+    // the number of arguments the native expects
+    pub fn arity(self) -> usize {
+        match self {
+            Native::Clock | Native::GcStats => 0,
+        }
+    }
+
+    pub fn call(self, heap: &Heap) -> NativeValue {
+        match self {
+            Native::Clock => {
+                let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+                NativeValue::Number(now.as_secs_f64())
+            }
+            Native::GcStats => {
+                let stats = heap.stats();
+                NativeValue::Instance {
+                    class: "GcStats",
+                    fields: vec![
+                        ("bytesAllocated", stats.bytes_allocated as f64),
+                        ("collections", stats.collections as f64),
+                    ],
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,376 @@
+use std::rc::Rc;
+
+use crate::interner::Symbol;
+use crate::parser::ast::*;
+use crate::parser::visitor::Visitor;
+use crate::token::{Token, TokenType};
+
+// Simplify the program before running it: the operations on literals
+// are computed once here instead of every time they are evaluated,
+// the branches that can never run are removed and the groupings,
+// only needed to parse the expressions, are dropped.
+//
+// The optimizer runs after the resolver, so the static errors in the
+// removed code are still reported, and it keeps the ids of the variable
+// expressions, which the interpreter uses to find the resolved scopes.
+// An operation is only computed when it can't fail: the ones with
+// invalid operands, e.g. `-"x"`, are kept as they are so that they
+// still fail at runtime.
+pub fn optimize(statements: &[Stmt]) -> Vec<Stmt> {
+    let optimizer = Optimizer;
+    statements
+        .iter()
+        .filter_map(|stmt| optimizer.visit_statement(stmt))
+        .collect()
+}
+
+// the value of a literal expression
+#[derive(PartialEq)]
+enum Constant {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    String(Symbol),
+}
+
+impl Constant {
+    fn of(expr: &Expression) -> Option<Constant> {
+        match expr {
+            Expression::Literal(literal) => Some(match literal {
+                LiteralExpression::Nil(_) => Constant::Nil,
+                LiteralExpression::Bool(token) => Constant::Bool(token.r#type == TokenType::True),
+                LiteralExpression::Number(token) => match token.r#type {
+                    TokenType::Number(value) => Constant::Number(value),
+                    _ => unreachable!("number literals are built from number tokens"),
+                },
+                LiteralExpression::String(token) => match token.r#type {
+                    TokenType::String(value) => Constant::String(value),
+                    _ => unreachable!("string literals are built from string tokens"),
+                },
+            }),
+            _ => None,
+        }
+    }
+
+    fn is_truthy(&self) -> bool {
+        match self {
+            Constant::Nil => false,
+            Constant::Bool(bool) => *bool,
+            _ => true,
+        }
+    }
+
+    // the literal expression for the value, located
+    // at the token of the operation that computed it
+    fn into_expression(self, location: &Token) -> Expression {
+        let token = |r#type, lexeme: &str| Token {
+            r#type,
+            lexeme: Rc::from(lexeme),
+            ..location.clone()
+        };
+        Expression::Literal(match self {
+            Constant::Nil => LiteralExpression::Nil(token(TokenType::Nil, "nil")),
+            Constant::Bool(true) => LiteralExpression::Bool(token(TokenType::True, "true")),
+            Constant::Bool(false) => LiteralExpression::Bool(token(TokenType::False, "false")),
+            Constant::Number(value) => {
+                LiteralExpression::Number(token(TokenType::Number(value), &value.to_string()))
+            }
+            Constant::String(value) => LiteralExpression::String(token(
+                TokenType::String(value),
+                &format!("\"{}\"", value),
+            )),
+        })
+    }
+}
+
+// the result of a binary operation on two literals,
+// or None if it would be a runtime error
+fn fold_binary(operator: &TokenType, left: Constant, right: Constant) -> Option<Constant> {
+    use Constant::{Bool, Number};
+    let constant = match (operator, left, right) {
+        (TokenType::EqualEqual, left, right) => Bool(left == right),
+        (TokenType::BangEqual, left, right) => Bool(left != right),
+        (TokenType::Plus, Number(left), Number(right)) => Number(left + right),
+        (TokenType::Plus, Constant::String(left), Constant::String(right)) => {
+            Constant::String(Symbol::intern(&[left.string(), right.string()].concat()))
+        }
+        (TokenType::Minus, Number(left), Number(right)) => Number(left - right),
+        (TokenType::Star, Number(left), Number(right)) => Number(left * right),
+        (TokenType::Slash, Number(left), Number(right)) => Number(left / right),
+        (TokenType::Greater, Number(left), Number(right)) => Bool(left > right),
+        (TokenType::GreaterEqual, Number(left), Number(right)) => Bool(left >= right),
+        (TokenType::Less, Number(left), Number(right)) => Bool(left < right),
+        (TokenType::LessEqual, Number(left), Number(right)) => Bool(left <= right),
+        _ => return None,
+    };
+    Some(constant)
+}
+
+struct Optimizer;
+
+impl Optimizer {
+    fn optimize(&self, expr: &Expression) -> Box<Expression> {
+        Box::new(self.visit_expression(expr))
+    }
+
+    // a statement that must stay in place even if it was removed,
+    // e.g. the body of a loop
+    fn optimize_nested(&self, stmt: &Stmt) -> Box<Stmt> {
+        Box::new(
+            self.visit_statement(stmt)
+                .unwrap_or_else(|| Stmt::Block(BlockStatement(Vec::new()))),
+        )
+    }
+
+    fn optimize_function(&self, stmt: &FunctionStatement) -> Rc<FunctionStatement> {
+        Rc::new(FunctionStatement {
+            name: stmt.name.clone(),
+            params: stmt.params.clone(),
+            body: optimize(&stmt.body),
+        })
+    }
+}
+
+// The statements return None when they are removed
+impl Visitor<Expression, Option<Stmt>> for Optimizer {
+    fn visit_block_statement(&self, stmt: &BlockStatement) -> Option<Stmt> {
+        Some(Stmt::Block(BlockStatement(optimize(&stmt.0))))
+    }
+    fn visit_class_statement(&self, stmt: &ClassStatement) -> Option<Stmt> {
+        Some(Stmt::Class(ClassStatement {
+            name: stmt.name.clone(),
+            superclass: stmt.superclass.clone(),
+            methods: stmt
+                .methods
+                .iter()
+                .map(|method| self.optimize_function(method))
+                .collect(),
+        }))
+    }
+    fn visit_expression_statement(&self, stmt: &ExpressionStatement) -> Option<Stmt> {
+        Some(Stmt::Expression(ExpressionStatement(
+            self.visit_expression(&stmt.0),
+        )))
+    }
+    fn visit_function_statement(&self, stmt: &Rc<FunctionStatement>) -> Option<Stmt> {
+        Some(Stmt::Function(self.optimize_function(stmt)))
+    }
+    fn visit_if_statement(&self, stmt: &IfStatement) -> Option<Stmt> {
+        let condition = self.visit_expression(&stmt.condition);
+        // only the branch taken is kept
+        if let Some(constant) = Constant::of(&condition) {
+            return if constant.is_truthy() {
+                self.visit_statement(&stmt.then_branch)
+            } else {
+                stmt.else_branch
+                    .as_ref()
+                    .and_then(|else_branch| self.visit_statement(else_branch))
+            };
+        }
+        Some(Stmt::If(IfStatement {
+            condition,
+            then_branch: self.optimize_nested(&stmt.then_branch),
+            else_branch: stmt
+                .else_branch
+                .as_ref()
+                .map(|else_branch| self.optimize_nested(else_branch)),
+        }))
+    }
+    fn visit_print_statement(&self, stmt: &PrintStatement) -> Option<Stmt> {
+        Some(Stmt::Print(PrintStatement(self.visit_expression(&stmt.0))))
+    }
+    fn visit_return_statement(&self, stmt: &ReturnStatement) -> Option<Stmt> {
+        Some(Stmt::Return(ReturnStatement {
+            keyword: stmt.keyword.clone(),
+            value: stmt
+                .value
+                .as_ref()
+                .map(|value| self.visit_expression(value)),
+        }))
+    }
+    fn visit_var_statement(&self, stmt: &VarStatement) -> Option<Stmt> {
+        Some(Stmt::Var(VarStatement {
+            name: stmt.name.clone(),
+            initializer: stmt
+                .initializer
+                .as_ref()
+                .map(|initializer| self.visit_expression(initializer)),
+        }))
+    }
+    fn visit_while_statement(&self, stmt: &WhileStatement) -> Option<Stmt> {
+        let condition = self.visit_expression(&stmt.condition);
+        // a loop that never runs
+        if Constant::of(&condition).is_some_and(|constant| !constant.is_truthy()) {
+            return None;
+        }
+        Some(Stmt::While(WhileStatement {
+            condition,
+            body: self.optimize_nested(&stmt.body),
+        }))
+    }
+    fn visit_assign_expression(&self, expr: &AssignExpression) -> Expression {
+        Expression::Assign(AssignExpression {
+            id: expr.id,
+            name: expr.name.clone(),
+            value: self.optimize(&expr.value),
+        })
+    }
+    fn visit_binary_expression(&self, expr: &BinaryExpression) -> Expression {
+        let left = self.optimize(&expr.left);
+        let right = self.optimize(&expr.right);
+        if let (Some(left), Some(right)) = (Constant::of(&left), Constant::of(&right)) {
+            if let Some(constant) = fold_binary(&expr.operator.r#type, left, right) {
+                return constant.into_expression(&expr.operator);
+            }
+        }
+        Expression::Binary(BinaryExpression {
+            left,
+            operator: expr.operator.clone(),
+            right,
+        })
+    }
+    fn visit_call_expression(&self, expr: &CallExpression) -> Expression {
+        Expression::Call(CallExpression {
+            callee: self.optimize(&expr.callee),
+            paren: expr.paren.clone(),
+            arguments: expr
+                .arguments
+                .iter()
+                .map(|argument| self.visit_expression(argument))
+                .collect(),
+        })
+    }
+    fn visit_comma_expression(&self, expr: &CommaExpression) -> Expression {
+        let left = self.optimize(&expr.left);
+        let right = self.optimize(&expr.right);
+        // evaluating a literal has no effect
+        if Constant::of(&left).is_some() {
+            return *right;
+        }
+        Expression::Comma(CommaExpression { left, right })
+    }
+    fn visit_conditional_expression(&self, expr: &ConditionalExpression) -> Expression {
+        let condition = self.optimize(&expr.condition);
+        if let Some(constant) = Constant::of(&condition) {
+            return if constant.is_truthy() {
+                self.visit_expression(&expr.then_branch)
+            } else {
+                self.visit_expression(&expr.else_branch)
+            };
+        }
+        Expression::Conditional(ConditionalExpression {
+            condition,
+            then_branch: self.optimize(&expr.then_branch),
+            else_branch: self.optimize(&expr.else_branch),
+        })
+    }
+    fn visit_get_expression(&self, expr: &GetExpression) -> Expression {
+        Expression::Get(GetExpression {
+            object: self.optimize(&expr.object),
+            name: expr.name.clone(),
+        })
+    }
+    fn visit_grouping_expression(&self, expr: &GroupingExpression) -> Expression {
+        self.visit_expression(&expr.0)
+    }
+    fn visit_literal_expression(&self, expr: &LiteralExpression) -> Expression {
+        Expression::Literal(expr.clone())
+    }
+    fn visit_logical_expression(&self, expr: &LogicalExpression) -> Expression {
+        let left = self.optimize(&expr.left);
+        // the left operand decides whether the right one is evaluated
+        if let Some(constant) = Constant::of(&left) {
+            let short_circuits = match expr.operator.r#type {
+                TokenType::Or => constant.is_truthy(),
+                _ => !constant.is_truthy(),
+            };
+            return if short_circuits {
+                *left
+            } else {
+                self.visit_expression(&expr.right)
+            };
+        }
+        Expression::Logical(LogicalExpression {
+            left,
+            operator: expr.operator.clone(),
+            right: self.optimize(&expr.right),
+        })
+    }
+    fn visit_set_expression(&self, expr: &SetExpression) -> Expression {
+        Expression::Set(SetExpression {
+            object: self.optimize(&expr.object),
+            name: expr.name.clone(),
+            value: self.optimize(&expr.value),
+        })
+    }
+    fn visit_super_expression(&self, expr: &SuperExpression) -> Expression {
+        Expression::Super(expr.clone())
+    }
+    fn visit_this_expression(&self, expr: &ThisExpression) -> Expression {
+        Expression::This(expr.clone())
+    }
+    fn visit_unary_expression(&self, expr: &UnaryExpression) -> Expression {
+        let operand = self.optimize(&expr.operand);
+        let constant = match (&expr.operator.r#type, Constant::of(&operand)) {
+            (TokenType::Bang, Some(constant)) => Some(Constant::Bool(!constant.is_truthy())),
+            (TokenType::Minus, Some(Constant::Number(value))) => Some(Constant::Number(-value)),
+            _ => None,
+        };
+        match constant {
+            Some(constant) => constant.into_expression(&expr.operator),
+            None => Expression::Unary(UnaryExpression {
+                operator: expr.operator.clone(),
+                operand,
+            }),
+        }
+    }
+    fn visit_variable_expression(&self, expr: &VariableExpression) -> Expression {
+        Expression::Variable(expr.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+    use crate::parser::visitor::PrettyPrinter;
+    use crate::scanner::Scanner;
+
+    fn optimized(source: &str) -> Vec<String> {
+        let mut scanner = Scanner::new(source);
+        let (tokens, errors) = scanner.scan_tokens();
+        assert!(errors.is_empty());
+        let statements = parser::parse(tokens).ok().unwrap();
+        optimize(&statements)
+            .iter()
+            .map(|stmt| PrettyPrinter.visit_statement(stmt))
+            .collect()
+    }
+
+    #[test]
+    fn folds_constant_expressions() {
+        assert_eq!(optimized("print (1 + 2) * 3;"), ["(print 9)"]);
+        assert_eq!(optimized("print \"a\" + \"b\";"), ["(print \"ab\")"]);
+        assert_eq!(optimized("print 1 < 2 == !nil;"), ["(print true)"]);
+        assert_eq!(optimized("print -(-2);"), ["(print 2)"]);
+        assert_eq!(optimized("print 1 == \"1\";"), ["(print false)"]);
+    }
+
+    #[test]
+    fn keeps_runtime_errors() {
+        assert_eq!(optimized("print -\"x\";"), ["(print (- \"x\"))"]);
+        assert_eq!(optimized("print 1 + \"a\";"), ["(print (+ 1 \"a\"))"]);
+    }
+
+    #[test]
+    fn removes_dead_branches() {
+        assert!(optimized("if (false) print 1;").is_empty());
+        assert_eq!(
+            optimized("if (1 > 2) print 1; else print 2;"),
+            ["(print 2)"]
+        );
+        assert!(optimized("while (nil) print 1;").is_empty());
+        assert_eq!(optimized("print false ? a : b;"), ["(print b)"]);
+        assert_eq!(optimized("print nil or a;"), ["(print a)"]);
+    }
+}
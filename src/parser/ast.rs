@@ -1,5 +1,27 @@
+use std::rc::Rc;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
 use crate::token::Token;
 
+// Every expression that refers to a variable gets a unique id,
+// so that the resolver can record the scope the variable belongs to
+pub type ExpressionId = usize;
+
+fn next_expression_id() -> ExpressionId {
+    // the ids must stay unique across the whole session,
+    // since the REPL keeps the results of the resolver of
+    // the previous lines around
+    static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+    NEXT_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+#[derive(Debug, Clone)]
+pub struct AssignExpression {
+    pub id: ExpressionId,
+    pub name: Token,
+    pub value: Box<Expression>,
+}
+
 #[derive(Debug, Clone)]
 pub struct BinaryExpression {
     pub left: Box<Expression>,
@@ -7,21 +29,157 @@ pub struct BinaryExpression {
     pub right: Box<Expression>,
 }
 
+#[derive(Debug, Clone)]
+pub struct CallExpression {
+    pub callee: Box<Expression>,
+    // the closing parenthesis, used to report
+    // the location of errors caused by the call
+    pub paren: Token,
+    pub arguments: Vec<Expression>,
+}
+
+// `left, right` evaluates both operands and returns the right one
+#[derive(Debug, Clone)]
+pub struct CommaExpression {
+    pub left: Box<Expression>,
+    pub right: Box<Expression>,
+}
+
+// `condition ? then_branch : else_branch`
+#[derive(Debug, Clone)]
+pub struct ConditionalExpression {
+    pub condition: Box<Expression>,
+    pub then_branch: Box<Expression>,
+    pub else_branch: Box<Expression>,
+}
+
+#[derive(Debug, Clone)]
+pub struct GetExpression {
+    pub object: Box<Expression>,
+    pub name: Token,
+}
+
 #[derive(Debug, Clone)]
 pub struct GroupingExpression(pub Box<Expression>);
 
+#[derive(Debug, Clone)]
+pub struct LogicalExpression {
+    pub left: Box<Expression>,
+    pub operator: Token,
+    pub right: Box<Expression>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SetExpression {
+    pub object: Box<Expression>,
+    pub name: Token,
+    pub value: Box<Expression>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SuperExpression {
+    pub id: ExpressionId,
+    pub keyword: Token,
+    pub method: Token,
+}
+
+#[derive(Debug, Clone)]
+pub struct ThisExpression {
+    pub id: ExpressionId,
+    pub keyword: Token,
+}
+
 #[derive(Debug, Clone)]
 pub struct UnaryExpression {
     pub operator: Token,
     pub operand: Box<Expression>,
 }
 
+#[derive(Debug, Clone)]
+pub struct VariableExpression {
+    pub id: ExpressionId,
+    pub name: Token,
+}
+
 #[derive(Debug, Clone)]
 pub enum Expression {
+    Assign(AssignExpression),
     Binary(BinaryExpression),
+    Call(CallExpression),
+    Comma(CommaExpression),
+    Conditional(ConditionalExpression),
+    Get(GetExpression),
     Grouping(GroupingExpression),
     Literal(LiteralExpression),
+    Logical(LogicalExpression),
+    Set(SetExpression),
+    Super(SuperExpression),
+    This(ThisExpression),
     Unary(UnaryExpression),
+    Variable(VariableExpression),
+}
+
+#[derive(Debug, Clone)]
+pub struct BlockStatement(pub Vec<Stmt>);
+
+#[derive(Debug, Clone)]
+pub struct ClassStatement {
+    pub name: Token,
+    pub superclass: Option<VariableExpression>,
+    pub methods: Vec<Rc<FunctionStatement>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ExpressionStatement(pub Expression);
+
+#[derive(Debug, Clone)]
+pub struct FunctionStatement {
+    pub name: Token,
+    pub params: Vec<Token>,
+    pub body: Vec<Stmt>,
+}
+
+#[derive(Debug, Clone)]
+pub struct IfStatement {
+    pub condition: Expression,
+    pub then_branch: Box<Stmt>,
+    pub else_branch: Option<Box<Stmt>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct PrintStatement(pub Expression);
+
+#[derive(Debug, Clone)]
+pub struct ReturnStatement {
+    pub keyword: Token,
+    pub value: Option<Expression>,
+}
+
+#[derive(Debug, Clone)]
+pub struct VarStatement {
+    pub name: Token,
+    pub initializer: Option<Expression>,
+}
+
+#[derive(Debug, Clone)]
+pub struct WhileStatement {
+    pub condition: Expression,
+    pub body: Box<Stmt>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Stmt {
+    Block(BlockStatement),
+    Class(ClassStatement),
+    Expression(ExpressionStatement),
+    // function declarations are shared with the
+    // runtime function objects created from them
+    Function(Rc<FunctionStatement>),
+    If(IfStatement),
+    Print(PrintStatement),
+    Return(ReturnStatement),
+    Var(VarStatement),
+    While(WhileStatement),
 }
 
 #[derive(Debug, Clone)]
@@ -32,8 +190,25 @@ pub enum LiteralExpression {
     String(Token),
 }
 
+impl VariableExpression {
+    pub(super) fn new(name: Token) -> Self {
+        VariableExpression {
+            id: next_expression_id(),
+            name,
+        }
+    }
+}
+
 impl Expression {
-    fn binary(left: Expression, operator: Token, right: Expression) -> Self {
+    pub(super) fn assign(name: Token, value: Expression) -> Self {
+        Self::Assign(AssignExpression {
+            id: next_expression_id(),
+            name,
+            value: Box::new(value),
+        })
+    }
+
+    pub(super) fn binary(left: Expression, operator: Token, right: Expression) -> Self {
         Self::Binary(BinaryExpression {
             left: Box::new(left),
             operator,
@@ -41,30 +216,152 @@ impl Expression {
         })
     }
 
-    fn grouping(expr: Expression) -> Self {
+    pub(super) fn call(callee: Expression, paren: Token, arguments: Vec<Expression>) -> Self {
+        Self::Call(CallExpression {
+            callee: Box::new(callee),
+            paren,
+            arguments,
+        })
+    }
+
+    pub(super) fn comma(left: Expression, right: Expression) -> Self {
+        Self::Comma(CommaExpression {
+            left: Box::new(left),
+            right: Box::new(right),
+        })
+    }
+
+    pub(super) fn conditional(
+        condition: Expression,
+        then_branch: Expression,
+        else_branch: Expression,
+    ) -> Self {
+        Self::Conditional(ConditionalExpression {
+            condition: Box::new(condition),
+            then_branch: Box::new(then_branch),
+            else_branch: Box::new(else_branch),
+        })
+    }
+
+    pub(super) fn get(object: Expression, name: Token) -> Self {
+        Self::Get(GetExpression {
+            object: Box::new(object),
+            name,
+        })
+    }
+
+    pub(super) fn grouping(expr: Expression) -> Self {
         Self::Grouping(GroupingExpression(Box::new(expr)))
     }
 
-    fn unary(operator: Token, operand: Expression) -> Self {
+    pub(super) fn logical(left: Expression, operator: Token, right: Expression) -> Self {
+        Self::Logical(LogicalExpression {
+            left: Box::new(left),
+            operator,
+            right: Box::new(right),
+        })
+    }
+
+    pub(super) fn set(object: Expression, name: Token, value: Expression) -> Self {
+        Self::Set(SetExpression {
+            object: Box::new(object),
+            name,
+            value: Box::new(value),
+        })
+    }
+
+    pub(super) fn super_method(keyword: Token, method: Token) -> Self {
+        Self::Super(SuperExpression {
+            id: next_expression_id(),
+            keyword,
+            method,
+        })
+    }
+
+    pub(super) fn this(keyword: Token) -> Self {
+        Self::This(ThisExpression {
+            id: next_expression_id(),
+            keyword,
+        })
+    }
+
+    pub(super) fn unary(operator: Token, operand: Expression) -> Self {
         Self::Unary(UnaryExpression {
             operator,
             operand: Box::new(operand),
         })
     }
 
-    fn bool(token: Token) -> Self {
+    pub(super) fn bool(token: Token) -> Self {
         Self::Literal(LiteralExpression::Bool(token))
     }
 
-    fn nil(token: Token) -> Self {
+    pub(super) fn nil(token: Token) -> Self {
         Self::Literal(LiteralExpression::Nil(token))
     }
 
-    fn number(token: Token) -> Self {
+    pub(super) fn number(token: Token) -> Self {
         Self::Literal(LiteralExpression::Number(token))
     }
 
-    fn string(token: Token) -> Self {
+    pub(super) fn string(token: Token) -> Self {
         Self::Literal(LiteralExpression::String(token))
     }
+
+    pub(super) fn variable(name: Token) -> Self {
+        Self::Variable(VariableExpression::new(name))
+    }
+}
+
+impl Stmt {
+    pub(super) fn block(statements: Vec<Stmt>) -> Self {
+        Self::Block(BlockStatement(statements))
+    }
+
+    pub(super) fn expression(expr: Expression) -> Self {
+        Self::Expression(ExpressionStatement(expr))
+    }
+
+    pub(super) fn class(
+        name: Token,
+        superclass: Option<VariableExpression>,
+        methods: Vec<Rc<FunctionStatement>>,
+    ) -> Self {
+        Self::Class(ClassStatement {
+            name,
+            superclass,
+            methods,
+        })
+    }
+
+    pub(super) fn r#if(
+        condition: Expression,
+        then_branch: Stmt,
+        else_branch: Option<Stmt>,
+    ) -> Self {
+        Self::If(IfStatement {
+            condition,
+            then_branch: Box::new(then_branch),
+            else_branch: else_branch.map(Box::new),
+        })
+    }
+
+    pub(super) fn print(expr: Expression) -> Self {
+        Self::Print(PrintStatement(expr))
+    }
+
+    pub(super) fn r#return(keyword: Token, value: Option<Expression>) -> Self {
+        Self::Return(ReturnStatement { keyword, value })
+    }
+
+    pub(super) fn var(name: Token, initializer: Option<Expression>) -> Self {
+        Self::Var(VarStatement { name, initializer })
+    }
+
+    pub(super) fn r#while(condition: Expression, body: Stmt) -> Self {
+        Self::While(WhileStatement {
+            condition,
+            body: Box::new(body),
+        })
+    }
 }
@@ -1,11 +1,13 @@
 pub(crate) mod ast;
+#[allow(clippy::module_inception)]
 mod parser;
 pub mod visitor;
 
 use crate::token::Token;
+pub use parser::ParseError;
 use parser::Parser;
 
-pub fn parse(tokens: Vec<Token>) -> ast::Expression {
+pub fn parse(tokens: Vec<Token>) -> Result<Vec<ast::Stmt>, Vec<ParseError>> {
     let mut parser = Parser::new(tokens);
     parser.parse()
 }
@@ -1,162 +1,716 @@
+use std::rc::Rc;
+
 use multipeek::{multipeek, MultiPeek};
 
 use crate::token::{Token, TokenType};
 
-use super::ast::{
-    BinaryExpression, Expression, GroupingExpression, LiteralExpression, UnaryExpression,
-};
+use super::ast::{Expression, FunctionStatement, Stmt, VariableExpression};
+
+// the maximum number of arguments of a function call
+const MAX_ARGUMENTS: usize = 255;
+
+pub struct ParseError {
+    pub message: String,
+    pub token: Token,
+}
 
 pub struct Parser {
     tokens: MultiPeek<std::vec::IntoIter<Token>>,
-    current: u64,
+    // all the syntax errors found so far
+    errors: Vec<ParseError>,
+    // the number of blocks and class bodies being parsed
+    depth: usize,
 }
 
 impl Parser {
     pub fn new(tokens: Vec<Token>) -> Self {
         Parser {
-            tokens: multipeek(tokens.into_iter()),
-            current: 0,
+            tokens: multipeek(tokens),
+            errors: Vec::new(),
+            depth: 0,
         }
     }
 
-    pub fn parse(&mut self) -> Expression {
-        self.expression()
+    // parse the whole program, going on after a syntax error
+    // so that all the errors are reported in a single run
+    pub fn parse(&mut self) -> Result<Vec<Stmt>, Vec<ParseError>> {
+        let mut statements = Vec::new();
+        while !self.is_at_end() {
+            if let Some(stmt) = self.declaration() {
+                statements.push(stmt);
+            }
+        }
+
+        if self.errors.is_empty() {
+            Ok(statements)
+        } else {
+            Err(std::mem::take(&mut self.errors))
+        }
     }
 
-    fn expression(&mut self) -> Expression {
-        self.equality()
+    fn is_at_end(&mut self) -> bool {
+        self.tokens.peek().unwrap().r#type == TokenType::EOF
     }
 
-    fn equality(&mut self) -> Expression {
-        let mut expr = self.comparison();
+    fn error(&mut self, message: &str) -> ParseError {
+        ParseError {
+            message: message.to_string(),
+            token: self.tokens.peek().unwrap().clone(),
+        }
+    }
 
-        loop {
-            match self.tokens.peek().unwrap().r#type {
-                TokenType::BangEqual | TokenType::EqualEqual => {
-                    let operator = self.tokens.next().unwrap();
-                    let right = self.comparison();
-                    expr = Expression::Binary(BinaryExpression {
-                        left: Box::new(expr),
-                        operator,
-                        right: Box::new(right),
-                    });
-                    continue;
+    // consume the next token if it's of the expected type
+    fn consume(&mut self, r#type: TokenType, message: &str) -> Result<Token, ParseError> {
+        if self.tokens.peek().unwrap().r#type != r#type {
+            return Err(self.error(message));
+        }
+        Ok(self.tokens.next().unwrap())
+    }
+
+    // consume the next token if it's an identifier
+    fn consume_identifier(&mut self, message: &str) -> Result<Token, ParseError> {
+        if !matches!(self.tokens.peek().unwrap().r#type, TokenType::Identifier(_)) {
+            return Err(self.error(message));
+        }
+        Ok(self.tokens.next().unwrap())
+    }
+
+    // After a syntax error, discard the tokens until the beginning
+    // of the next statement, so that the parser can go on without
+    // reporting a cascade of errors caused by the first one.
+    // A statement begins right after a semicolon or a block skipped
+    // along with the statement, or with a keyword. Inside a block,
+    // its closing brace is left for the block to consume
+    fn synchronize(&mut self) {
+        // the error can be at the end of the block
+        if self.depth > 0 && self.tokens.peek().unwrap().r#type == TokenType::RightBrace {
+            return;
+        }
+        // the braces opened by the tokens skipped so far
+        let mut braces = 0;
+        while !self.is_at_end() {
+            match self.tokens.next().unwrap().r#type {
+                TokenType::LeftBrace => braces += 1,
+                TokenType::RightBrace if braces > 0 => {
+                    braces -= 1;
+                    if braces == 0 {
+                        return;
+                    }
                 }
+                TokenType::Semicolon if braces == 0 => return,
                 _ => {}
             }
-            break;
+            if braces > 0 {
+                continue;
+            }
+            match self.tokens.peek().unwrap().r#type {
+                TokenType::RightBrace if self.depth > 0 => return,
+                TokenType::Class
+                | TokenType::Fun
+                | TokenType::Var
+                | TokenType::For
+                | TokenType::If
+                | TokenType::While
+                | TokenType::Print
+                | TokenType::Return => return,
+                _ => {}
+            }
+        }
+    }
+
+    // parse a declaration, recording its syntax error, if any,
+    // and skipping the rest of it so that the parser can go on
+    fn declaration(&mut self) -> Option<Stmt> {
+        match self.try_declaration() {
+            Ok(stmt) => Some(stmt),
+            Err(error) => {
+                self.errors.push(error);
+                self.synchronize();
+                None
+            }
         }
+    }
 
-        expr
+    fn try_declaration(&mut self) -> Result<Stmt, ParseError> {
+        match self.tokens.peek().unwrap().r#type {
+            TokenType::Class => self.class_declaration(),
+            TokenType::Fun => {
+                // consume the "fun" keyword
+                self.tokens.next();
+                Ok(Stmt::Function(self.function("function")?))
+            }
+            TokenType::Var => self.var_declaration(),
+            _ => self.statement(),
+        }
     }
 
-    fn comparison(&mut self) -> Expression {
-        let mut expr = self.term();
+    fn class_declaration(&mut self) -> Result<Stmt, ParseError> {
+        // consume the "class" keyword
+        self.tokens.next();
+        let name = self.consume_identifier("expected class name")?;
 
-        loop {
+        let mut superclass = None;
+        if self.tokens.peek().unwrap().r#type == TokenType::Less {
+            // consume the "<"
+            self.tokens.next();
+            let superclass_name = self.consume_identifier("expected superclass name")?;
+            superclass = Some(VariableExpression::new(superclass_name));
+        }
+
+        self.consume(TokenType::LeftBrace, "expected '{' before class body")?;
+
+        let mut methods = Vec::new();
+        self.depth += 1;
+        while self.tokens.peek().unwrap().r#type != TokenType::RightBrace && !self.is_at_end() {
+            // methods are declared like functions, without the "fun" keyword
+            match self.function("method") {
+                Ok(method) => methods.push(method),
+                Err(error) => {
+                    self.errors.push(error);
+                    self.skip_method();
+                }
+            }
+        }
+        self.depth -= 1;
+        self.consume(TokenType::RightBrace, "expected '}' after class body")?;
+        Ok(Stmt::class(name, superclass, methods))
+    }
+
+    // After a syntax error in the declaration of a method, discard
+    // the tokens up to the end of its body, or of the class body
+    // if the method has no body, so that the next method can be parsed
+    fn skip_method(&mut self) {
+        let mut braces = 0;
+        while !self.is_at_end() {
             match self.tokens.peek().unwrap().r#type {
-                TokenType::Less
-                | TokenType::LessEqual
-                | TokenType::Greater
-                | TokenType::GreaterEqual => {
-                    let operator = self.tokens.next().unwrap();
-                    let right = self.term();
-                    expr = Expression::Binary(BinaryExpression {
-                        left: Box::new(expr),
-                        operator,
-                        right: Box::new(right),
-                    });
-                    continue;
+                TokenType::LeftBrace => braces += 1,
+                TokenType::RightBrace if braces == 0 => return,
+                TokenType::RightBrace => {
+                    braces -= 1;
+                    if braces == 0 {
+                        self.tokens.next();
+                        return;
+                    }
                 }
                 _ => {}
             }
-            break;
+            self.tokens.next();
         }
-        expr
     }
 
-    fn term(&mut self) -> Expression {
-        let mut expr = self.factor();
+    // the kind is used to report errors for both functions and methods
+    fn function(&mut self, kind: &str) -> Result<Rc<FunctionStatement>, ParseError> {
+        let name = self.consume_identifier(&format!("expected {} name", kind))?;
+        self.consume(
+            TokenType::LeftParen,
+            &format!("expected '(' after {} name", kind),
+        )?;
+        let mut params = Vec::new();
+        if self.tokens.peek().unwrap().r#type != TokenType::RightParen {
+            loop {
+                if params.len() >= MAX_ARGUMENTS {
+                    // the parser is not in a confused state,
+                    // so the error is reported without unwinding
+                    let error = self.error(&format!(
+                        "can't have more than {} parameters",
+                        MAX_ARGUMENTS
+                    ));
+                    self.errors.push(error);
+                }
+                params.push(self.consume_identifier("expected parameter name")?);
+                if self.tokens.peek().unwrap().r#type != TokenType::Comma {
+                    break;
+                }
+                self.tokens.next();
+            }
+        }
+        self.consume(TokenType::RightParen, "expected ')' after parameters")?;
 
-        loop {
-            match self.tokens.peek().unwrap().r#type {
-                TokenType::Minus | TokenType::Plus => {
-                    let operator = self.tokens.next().unwrap();
-                    let right = self.factor();
-                    expr = Expression::Binary(BinaryExpression {
-                        left: Box::new(expr),
-                        operator,
-                        right: Box::new(right),
+        if self.tokens.peek().unwrap().r#type != TokenType::LeftBrace {
+            return Err(self.error(&format!("expected '{{' before {} body", kind)));
+        }
+        let body = self.block()?;
+        Ok(Rc::new(FunctionStatement { name, params, body }))
+    }
+
+    fn var_declaration(&mut self) -> Result<Stmt, ParseError> {
+        // consume the "var" keyword
+        self.tokens.next();
+        let name = self.consume_identifier("expected variable name")?;
+
+        let mut initializer = None;
+        if self.tokens.peek().unwrap().r#type == TokenType::Equal {
+            // consume the "="
+            self.tokens.next();
+            initializer = Some(self.expression()?);
+        }
+        self.consume(
+            TokenType::Semicolon,
+            "expected ';' after variable declaration",
+        )?;
+        Ok(Stmt::var(name, initializer))
+    }
+
+    fn statement(&mut self) -> Result<Stmt, ParseError> {
+        match self.tokens.peek().unwrap().r#type {
+            TokenType::For => self.for_statement(),
+            TokenType::If => self.if_statement(),
+            TokenType::Print => self.print_statement(),
+            TokenType::Return => self.return_statement(),
+            TokenType::While => self.while_statement(),
+            TokenType::LeftBrace => Ok(Stmt::block(self.block()?)),
+            _ => self.expression_statement(),
+        }
+    }
+
+    fn block(&mut self) -> Result<Vec<Stmt>, ParseError> {
+        // consume the "{"
+        self.tokens.next();
+        let mut statements = Vec::new();
+        self.depth += 1;
+        while self.tokens.peek().unwrap().r#type != TokenType::RightBrace && !self.is_at_end() {
+            // the errors are recovered from inside the block,
+            // so that its closing brace is still matched
+            if let Some(stmt) = self.declaration() {
+                statements.push(stmt);
+            }
+        }
+        self.depth -= 1;
+        self.consume(TokenType::RightBrace, "expected '}' after block")?;
+        Ok(statements)
+    }
+
+    // the for loop is syntactic sugar: it gets desugared into
+    // a block with the initializer followed by a while loop
+    // whose body runs the original body and then the increment
+    fn for_statement(&mut self) -> Result<Stmt, ParseError> {
+        // consume the "for" keyword
+        self.tokens.next();
+        self.consume(TokenType::LeftParen, "expected '(' after 'for'")?;
+
+        let initializer = match self.tokens.peek().unwrap().r#type {
+            TokenType::Semicolon => {
+                self.tokens.next();
+                None
+            }
+            TokenType::Var => Some(self.var_declaration()?),
+            _ => Some(self.expression_statement()?),
+        };
+
+        let condition = match self.tokens.peek().unwrap().r#type {
+            TokenType::Semicolon => None,
+            _ => Some(self.expression()?),
+        };
+        let semicolon = self.consume(TokenType::Semicolon, "expected ';' after loop condition")?;
+
+        let increment = match self.tokens.peek().unwrap().r#type {
+            TokenType::RightParen => None,
+            _ => Some(self.expression()?),
+        };
+        self.consume(TokenType::RightParen, "expected ')' after for clauses")?;
+
+        let mut body = self.statement()?;
+        if let Some(increment) = increment {
+            body = Stmt::block(vec![body, Stmt::expression(increment)]);
+        }
+        // an omitted condition loops forever
+        let condition = condition.unwrap_or_else(|| {
+            Expression::bool(Token {
+                r#type: TokenType::True,
+                lexeme: Rc::from("true"),
+                ..semicolon
+            })
+        });
+        body = Stmt::r#while(condition, body);
+        if let Some(initializer) = initializer {
+            body = Stmt::block(vec![initializer, body]);
+        }
+        Ok(body)
+    }
+
+    fn if_statement(&mut self) -> Result<Stmt, ParseError> {
+        // consume the "if" keyword
+        self.tokens.next();
+        self.consume(TokenType::LeftParen, "expected '(' after 'if'")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, "expected ')' after if condition")?;
+
+        let then_branch = self.statement()?;
+        // the else is bound to the nearest if that precedes it,
+        // since we eagerly look for it before returning
+        let mut else_branch = None;
+        if self.tokens.peek().unwrap().r#type == TokenType::Else {
+            self.tokens.next();
+            else_branch = Some(self.statement()?);
+        }
+        Ok(Stmt::r#if(condition, then_branch, else_branch))
+    }
+
+    fn return_statement(&mut self) -> Result<Stmt, ParseError> {
+        let keyword = self.tokens.next().unwrap();
+        let value = match self.tokens.peek().unwrap().r#type {
+            TokenType::Semicolon => None,
+            _ => Some(self.expression()?),
+        };
+        self.consume(TokenType::Semicolon, "expected ';' after return value")?;
+        Ok(Stmt::r#return(keyword, value))
+    }
+
+    fn while_statement(&mut self) -> Result<Stmt, ParseError> {
+        // consume the "while" keyword
+        self.tokens.next();
+        self.consume(TokenType::LeftParen, "expected '(' after 'while'")?;
+        let condition = self.expression()?;
+        self.consume(TokenType::RightParen, "expected ')' after condition")?;
+        let body = self.statement()?;
+        Ok(Stmt::r#while(condition, body))
+    }
+
+    fn print_statement(&mut self) -> Result<Stmt, ParseError> {
+        // consume the "print" keyword
+        self.tokens.next();
+        let expr = self.expression()?;
+        self.consume(TokenType::Semicolon, "expected ';' after value")?;
+        Ok(Stmt::print(expr))
+    }
+
+    fn expression_statement(&mut self) -> Result<Stmt, ParseError> {
+        let expr = self.expression()?;
+        self.consume(TokenType::Semicolon, "expected ';' after expression")?;
+        Ok(Stmt::expression(expr))
+    }
+
+    fn expression(&mut self) -> Result<Expression, ParseError> {
+        self.comma()
+    }
+
+    // the comma operator has the lowest precedence and is left-associative
+    fn comma(&mut self) -> Result<Expression, ParseError> {
+        let mut expr = self.assignment()?;
+
+        while self.tokens.peek().unwrap().r#type == TokenType::Comma {
+            self.tokens.next();
+            let right = self.assignment()?;
+            expr = Expression::comma(expr, right);
+        }
+
+        Ok(expr)
+    }
+
+    // assignment is right-associative, so instead of looping
+    // we recursively call assignment() to parse the right-hand side
+    fn assignment(&mut self) -> Result<Expression, ParseError> {
+        let expr = self.conditional()?;
+
+        if self.tokens.peek().unwrap().r#type == TokenType::Equal {
+            let equals = self.tokens.next().unwrap();
+            let value = self.assignment()?;
+
+            return match expr {
+                Expression::Variable(variable) => Ok(Expression::assign(variable.name, value)),
+                // a property access on the left-hand side becomes a property assignment
+                Expression::Get(get) => Ok(Expression::set(*get.object, get.name, value)),
+                // the parser is not in a confused state, so the
+                // error is reported without unwinding and the
+                // left-hand side is kept in place of the assignment
+                _ => {
+                    self.errors.push(ParseError {
+                        message: "invalid assignment target".to_string(),
+                        token: equals,
                     });
-                    continue;
+                    Ok(expr)
                 }
-                _ => {}
+            };
+        }
+
+        Ok(expr)
+    }
+
+    // The conditional is right-associative, so `a ? b : c ? d : e`
+    // is `a ? b : (c ? d : e)`. As in C, the middle operand can be
+    // any expression, since it's delimited by "?" and ":"
+    fn conditional(&mut self) -> Result<Expression, ParseError> {
+        let condition = self.or()?;
+
+        if self.tokens.peek().unwrap().r#type == TokenType::Question {
+            self.tokens.next();
+            let then_branch = self.expression()?;
+            self.consume(
+                TokenType::Colon,
+                "expected ':' after the then branch of the conditional",
+            )?;
+            let else_branch = self.conditional()?;
+            return Ok(Expression::conditional(condition, then_branch, else_branch));
+        }
+
+        Ok(condition)
+    }
+
+    fn or(&mut self) -> Result<Expression, ParseError> {
+        let mut expr = self.and()?;
+
+        while self.tokens.peek().unwrap().r#type == TokenType::Or {
+            let operator = self.tokens.next().unwrap();
+            let right = self.and()?;
+            expr = Expression::logical(expr, operator, right);
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expression, ParseError> {
+        let mut expr = self.equality()?;
+
+        while self.tokens.peek().unwrap().r#type == TokenType::And {
+            let operator = self.tokens.next().unwrap();
+            let right = self.equality()?;
+            expr = Expression::logical(expr, operator, right);
+        }
+        Ok(expr)
+    }
+
+    fn equality(&mut self) -> Result<Expression, ParseError> {
+        let mut expr = self.comparison()?;
+
+        while let TokenType::BangEqual | TokenType::EqualEqual = self.tokens.peek().unwrap().r#type
+        {
+            let operator = self.tokens.next().unwrap();
+            let right = self.comparison()?;
+            expr = Expression::binary(expr, operator, right);
+        }
+
+        Ok(expr)
+    }
+
+    fn comparison(&mut self) -> Result<Expression, ParseError> {
+        let mut expr = self.term()?;
+
+        while let TokenType::Less
+        | TokenType::LessEqual
+        | TokenType::Greater
+        | TokenType::GreaterEqual = self.tokens.peek().unwrap().r#type
+        {
+            let operator = self.tokens.next().unwrap();
+            let right = self.term()?;
+            expr = Expression::binary(expr, operator, right);
+        }
+        Ok(expr)
+    }
+
+    fn term(&mut self) -> Result<Expression, ParseError> {
+        let mut expr = self.factor()?;
+
+        while let TokenType::Minus | TokenType::Plus = self.tokens.peek().unwrap().r#type {
+            let operator = self.tokens.next().unwrap();
+            let right = self.factor()?;
+            expr = Expression::binary(expr, operator, right);
+        }
+        Ok(expr)
+    }
+
+    fn factor(&mut self) -> Result<Expression, ParseError> {
+        let mut expr = self.unary()?;
+
+        while let TokenType::Slash | TokenType::Star = self.tokens.peek().unwrap().r#type {
+            let operator = self.tokens.next().unwrap();
+            let right = self.unary()?;
+            expr = Expression::binary(expr, operator, right);
+        }
+        Ok(expr)
+    }
+
+    fn unary(&mut self) -> Result<Expression, ParseError> {
+        match self.tokens.peek().unwrap().r#type {
+            TokenType::Bang | TokenType::Minus => {
+                let operator = self.tokens.next().unwrap();
+                Ok(Expression::unary(operator, self.unary()?))
             }
-            break;
+            _ => self.call(),
         }
-        expr
     }
 
-    fn factor(&mut self) -> Expression {
-        let mut expr = self.unary();
+    fn call(&mut self) -> Result<Expression, ParseError> {
+        let mut expr = self.primary()?;
 
         loop {
             match self.tokens.peek().unwrap().r#type {
-                TokenType::Slash | TokenType::Star => {
-                    let operator = self.tokens.next().unwrap();
-                    let right = self.unary();
-                    expr = Expression::Binary(BinaryExpression {
-                        left: Box::new(expr),
-                        operator,
-                        right: Box::new(right),
-                    });
-                    continue;
+                TokenType::LeftParen => {
+                    // consume the left parenthesis
+                    self.tokens.next();
+                    expr = self.finish_call(expr)?;
                 }
-                _ => {}
+                TokenType::Dot => {
+                    // consume the dot
+                    self.tokens.next();
+                    let name = self.consume_identifier("expected property name after '.'")?;
+                    expr = Expression::get(expr, name);
+                }
+                _ => break,
             }
-            break;
         }
-        expr
+        Ok(expr)
     }
 
-    fn unary(&mut self) -> Expression {
-        match self.tokens.peek().unwrap().r#type {
-            TokenType::Bang | TokenType::Minus => Expression::Unary(UnaryExpression {
-                operator: self.tokens.next().unwrap(),
-                operand: Box::new(self.unary()),
-            }),
-            _ => self.primary(),
+    fn finish_call(&mut self, callee: Expression) -> Result<Expression, ParseError> {
+        let mut arguments = Vec::new();
+        if self.tokens.peek().unwrap().r#type != TokenType::RightParen {
+            loop {
+                if arguments.len() >= MAX_ARGUMENTS {
+                    // the parser is not in a confused state,
+                    // so the error is reported without unwinding
+                    let error =
+                        self.error(&format!("can't have more than {} arguments", MAX_ARGUMENTS));
+                    self.errors.push(error);
+                }
+                // the arguments are separated by commas,
+                // so they can't be comma expressions
+                arguments.push(self.assignment()?);
+                if self.tokens.peek().unwrap().r#type != TokenType::Comma {
+                    break;
+                }
+                self.tokens.next();
+            }
         }
+        let paren = self.consume(TokenType::RightParen, "expected ')' after arguments")?;
+        Ok(Expression::call(callee, paren, arguments))
     }
 
-    fn primary(&mut self) -> Expression {
+    fn primary(&mut self) -> Result<Expression, ParseError> {
         match self.tokens.peek().unwrap().r#type {
-            TokenType::True | TokenType::False => {
-                Expression::Literal(LiteralExpression::Bool(self.tokens.next().unwrap()))
-            }
-            TokenType::Nil => {
-                Expression::Literal(LiteralExpression::Nil(self.tokens.next().unwrap()))
-            }
-            TokenType::String(_) => {
-                Expression::Literal(LiteralExpression::String(self.tokens.next().unwrap()))
-            }
-            TokenType::Number(_) => {
-                Expression::Literal(LiteralExpression::Number(self.tokens.next().unwrap()))
+            TokenType::True | TokenType::False => Ok(Expression::bool(self.tokens.next().unwrap())),
+            TokenType::Nil => Ok(Expression::nil(self.tokens.next().unwrap())),
+            TokenType::String(_) => Ok(Expression::string(self.tokens.next().unwrap())),
+            TokenType::Number(_) => Ok(Expression::number(self.tokens.next().unwrap())),
+            TokenType::Super => {
+                let keyword = self.tokens.next().unwrap();
+                self.consume(TokenType::Dot, "expected '.' after 'super'")?;
+                let method = self.consume_identifier("expected superclass method name")?;
+                Ok(Expression::super_method(keyword, method))
             }
+            TokenType::This => Ok(Expression::this(self.tokens.next().unwrap())),
+            TokenType::Identifier(_) => Ok(Expression::variable(self.tokens.next().unwrap())),
             TokenType::LeftParen => {
                 // consume the left parethesis
                 self.tokens.next();
-                let expr = self.expression();
-                // temporary panic
-                // TODO: substitute with proper error handling
-                if self.tokens.peek().unwrap().r#type != TokenType::RightParen {
-                    panic!("Error: expected ')' after expression")
-                }
-                // consume the right parethesis
-                self.tokens.next();
-                Expression::Grouping(GroupingExpression(Box::new(expr)))
+                let expr = self.expression()?;
+                self.consume(TokenType::RightParen, "expected ')' after expression")?;
+                Ok(Expression::grouping(expr))
+            }
+            // error productions for a binary operator without its left
+            // operand. The right one is parsed at the operator's precedence
+            TokenType::BangEqual | TokenType::EqualEqual => {
+                self.missing_left_operand(Self::equality)
             }
-            _ => panic!("Error: token not expected"),
+            TokenType::Greater
+            | TokenType::GreaterEqual
+            | TokenType::Less
+            | TokenType::LessEqual => self.missing_left_operand(Self::comparison),
+            // "-" is not here, since it's also a unary operator
+            TokenType::Plus => self.missing_left_operand(Self::term),
+            TokenType::Slash | TokenType::Star => self.missing_left_operand(Self::factor),
+            _ => Err(self.error("expected expression")),
+        }
+    }
+
+    // Report a binary operator found where an expression should begin,
+    // e.g. `* 3`. Its right operand is parsed and discarded, so that
+    // the parser synchronizes after it instead of in the middle of it
+    fn missing_left_operand(
+        &mut self,
+        operand: fn(&mut Self) -> Result<Expression, ParseError>,
+    ) -> Result<Expression, ParseError> {
+        let operator = self.tokens.next().unwrap();
+        let error = ParseError {
+            message: format!("missing left-hand operand for `{}`", operator.lexeme),
+            token: operator,
+        };
+        if let Err(operand_error) = operand(self) {
+            self.errors.push(error);
+            return Err(operand_error);
+        }
+        Err(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::Scanner;
+
+    // the line and the message of each syntax error in the source
+    fn parse_errors(source: &str) -> Vec<(u64, String)> {
+        let mut scanner = Scanner::new(source);
+        let (tokens, lexical_errors) = scanner.scan_tokens();
+        assert!(lexical_errors.is_empty(), "unexpected lexical errors");
+        match Parser::new(tokens).parse() {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors
+                .into_iter()
+                .map(|error| (error.token.line, error.message))
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn recovers_inside_function_bodies() {
+        assert_eq!(
+            parse_errors("fun f() {\n var x = ;\n print 1;\n}"),
+            [(2, "expected expression".to_string())]
+        );
+    }
+
+    #[test]
+    fn recovers_inside_nested_blocks() {
+        assert_eq!(
+            parse_errors("{ var x = 1 + }\nprint 2;"),
+            [(1, "expected expression".to_string())]
+        );
+        assert_eq!(
+            parse_errors("{\n if (x { print 1; }\n print 3;\n}\nprint 2 +;"),
+            [
+                (2, "expected ')' after if condition".to_string()),
+                (5, "expected expression".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn recovers_inside_class_bodies() {
+        assert_eq!(
+            parse_errors("class A {\n m( { print 1; }\n n() { return 1 +; }\n o() {}\n}"),
+            [
+                (2, "expected parameter name".to_string()),
+                (3, "expected expression".to_string())
+            ]
+        );
+    }
+
+    #[test]
+    fn reports_missing_left_operands() {
+        let cases = [
+            ("== 1;", TokenType::EqualEqual),
+            ("< 2;", TokenType::Less),
+            ("+ 3;", TokenType::Plus),
+            ("* 4;", TokenType::Star),
+        ];
+        for (source, operator) in cases {
+            let source = format!("{}\nprint ;", source);
+            let (tokens, _) = Scanner::new(&source).scan_tokens();
+            let Err(errors) = Parser::new(tokens).parse() else {
+                panic!("expected syntax errors in {:?}", source);
+            };
+            assert_eq!(errors.len(), 2, "{:?}", source);
+            let lexeme = &source[..source.find(' ').unwrap()];
+            assert_eq!(
+                errors[0].message,
+                format!("missing left-hand operand for `{}`", lexeme)
+            );
+            assert_eq!(errors[0].token.r#type, operator);
+            assert_eq!(errors[0].token.lexeme.as_ref(), lexeme);
+            // the parser goes on after the operand
+            assert_eq!(
+                (errors[1].token.line, errors[1].message.as_str()),
+                (2, "expected expression")
+            );
         }
     }
 }
@@ -1,42 +1,184 @@
 use std::cell::RefCell;
 use std::fs::File;
-use std::io::{self, Write};
+use std::io::Write;
+use std::rc::Rc;
 
 use graphviz_rust::dot_generator::{attr, edge, graph, id, node, node_id};
 use graphviz_rust::dot_structures::{
-    Attribute, Edge, EdgeTy, Graph, Id, Node, NodeId, Stmt, Vertex,
+    Attribute, Edge, EdgeTy, Graph, Id, Node, NodeId, Stmt as DotStmt, Vertex,
 };
 use graphviz_rust::printer::PrinterContext;
 use graphviz_rust::{cmd::Format, exec};
 
 use crate::parser::ast::*;
 
-pub trait Visitor<T> {
+pub trait Visitor<T, S = T> {
+    fn visit_statement(&self, stmt: &Stmt) -> S {
+        match stmt {
+            Stmt::Block(block_statement) => Self::visit_block_statement(self, block_statement),
+            Stmt::Class(class_statement) => Self::visit_class_statement(self, class_statement),
+            Stmt::Expression(expression_statement) => {
+                Self::visit_expression_statement(self, expression_statement)
+            }
+            Stmt::Function(function_statement) => {
+                Self::visit_function_statement(self, function_statement)
+            }
+            Stmt::If(if_statement) => Self::visit_if_statement(self, if_statement),
+            Stmt::Print(print_statement) => Self::visit_print_statement(self, print_statement),
+            Stmt::Return(return_statement) => Self::visit_return_statement(self, return_statement),
+            Stmt::Var(var_statement) => Self::visit_var_statement(self, var_statement),
+            Stmt::While(while_statement) => Self::visit_while_statement(self, while_statement),
+        }
+    }
+    fn visit_block_statement(&self, stmt: &BlockStatement) -> S;
+    fn visit_class_statement(&self, stmt: &ClassStatement) -> S;
+    fn visit_expression_statement(&self, stmt: &ExpressionStatement) -> S;
+    fn visit_function_statement(&self, stmt: &Rc<FunctionStatement>) -> S;
+    fn visit_if_statement(&self, stmt: &IfStatement) -> S;
+    fn visit_print_statement(&self, stmt: &PrintStatement) -> S;
+    fn visit_return_statement(&self, stmt: &ReturnStatement) -> S;
+    fn visit_var_statement(&self, stmt: &VarStatement) -> S;
+    fn visit_while_statement(&self, stmt: &WhileStatement) -> S;
+
     fn visit_expression(&self, expr: &Expression) -> T {
         match expr {
+            Expression::Assign(assign_expression) => {
+                Self::visit_assign_expression(self, assign_expression)
+            }
             Expression::Binary(binary_expression) => {
                 Self::visit_binary_expression(self, binary_expression)
             }
+            Expression::Call(call_expression) => Self::visit_call_expression(self, call_expression),
+            Expression::Comma(comma_expression) => {
+                Self::visit_comma_expression(self, comma_expression)
+            }
+            Expression::Conditional(conditional_expression) => {
+                Self::visit_conditional_expression(self, conditional_expression)
+            }
+            Expression::Get(get_expression) => Self::visit_get_expression(self, get_expression),
             Expression::Grouping(grouping_expression) => {
                 Self::visit_grouping_expression(self, grouping_expression)
             }
             Expression::Literal(literal_expression) => {
                 Self::visit_literal_expression(self, literal_expression)
             }
+            Expression::Logical(logical_expression) => {
+                Self::visit_logical_expression(self, logical_expression)
+            }
+            Expression::Set(set_expression) => Self::visit_set_expression(self, set_expression),
+            Expression::Super(super_expression) => {
+                Self::visit_super_expression(self, super_expression)
+            }
+            Expression::This(this_expression) => Self::visit_this_expression(self, this_expression),
             Expression::Unary(unary_expression) => {
                 Self::visit_unary_expression(self, unary_expression)
             }
+            Expression::Variable(variable_expression) => {
+                Self::visit_variable_expression(self, variable_expression)
+            }
         }
     }
+    fn visit_assign_expression(&self, expr: &AssignExpression) -> T;
     fn visit_binary_expression(&self, expr: &BinaryExpression) -> T;
+    fn visit_call_expression(&self, expr: &CallExpression) -> T;
+    fn visit_comma_expression(&self, expr: &CommaExpression) -> T;
+    fn visit_conditional_expression(&self, expr: &ConditionalExpression) -> T;
+    fn visit_get_expression(&self, expr: &GetExpression) -> T;
     fn visit_grouping_expression(&self, expr: &GroupingExpression) -> T;
     fn visit_literal_expression(&self, expr: &LiteralExpression) -> T;
+    fn visit_logical_expression(&self, expr: &LogicalExpression) -> T;
+    fn visit_set_expression(&self, expr: &SetExpression) -> T;
+    fn visit_super_expression(&self, expr: &SuperExpression) -> T;
+    fn visit_this_expression(&self, expr: &ThisExpression) -> T;
     fn visit_unary_expression(&self, expr: &UnaryExpression) -> T;
+    fn visit_variable_expression(&self, expr: &VariableExpression) -> T;
 }
 
 pub struct PrettyPrinter;
 
 impl Visitor<String> for PrettyPrinter {
+    fn visit_block_statement(&self, stmt: &BlockStatement) -> String {
+        let mut block = String::from("(block");
+        for statement in &stmt.0 {
+            block.push(' ');
+            block.push_str(&Self::visit_statement(self, statement));
+        }
+        block.push(')');
+        block
+    }
+    fn visit_class_statement(&self, stmt: &ClassStatement) -> String {
+        let mut class = format!("(class {}", stmt.name.lexeme);
+        if let Some(superclass) = &stmt.superclass {
+            class.push_str(&format!(" < {}", superclass.name.lexeme));
+        }
+        for method in &stmt.methods {
+            class.push(' ');
+            class.push_str(&Self::visit_function_statement(self, method));
+        }
+        class.push(')');
+        class
+    }
+    fn visit_expression_statement(&self, stmt: &ExpressionStatement) -> String {
+        format!("(; {})", Self::visit_expression(self, &stmt.0))
+    }
+    fn visit_function_statement(&self, stmt: &Rc<FunctionStatement>) -> String {
+        let params: Vec<String> = stmt.params.iter().map(|p| p.lexeme.to_string()).collect();
+        let mut function = format!("(fun {} ({})", stmt.name.lexeme, params.join(" "));
+        for statement in &stmt.body {
+            function.push(' ');
+            function.push_str(&Self::visit_statement(self, statement));
+        }
+        function.push(')');
+        function
+    }
+    fn visit_if_statement(&self, stmt: &IfStatement) -> String {
+        match &stmt.else_branch {
+            Some(else_branch) => format!(
+                "(if {} {} {})",
+                Self::visit_expression(self, &stmt.condition),
+                Self::visit_statement(self, &stmt.then_branch),
+                Self::visit_statement(self, else_branch)
+            ),
+            None => format!(
+                "(if {} {})",
+                Self::visit_expression(self, &stmt.condition),
+                Self::visit_statement(self, &stmt.then_branch)
+            ),
+        }
+    }
+    fn visit_print_statement(&self, stmt: &PrintStatement) -> String {
+        format!("(print {})", Self::visit_expression(self, &stmt.0))
+    }
+    fn visit_return_statement(&self, stmt: &ReturnStatement) -> String {
+        match &stmt.value {
+            Some(value) => format!("(return {})", Self::visit_expression(self, value)),
+            None => "(return)".to_string(),
+        }
+    }
+    fn visit_var_statement(&self, stmt: &VarStatement) -> String {
+        match &stmt.initializer {
+            Some(initializer) => format!(
+                "(var {} {})",
+                stmt.name.lexeme,
+                Self::visit_expression(self, initializer)
+            ),
+            None => format!("(var {})", stmt.name.lexeme),
+        }
+    }
+    fn visit_while_statement(&self, stmt: &WhileStatement) -> String {
+        format!(
+            "(while {} {})",
+            Self::visit_expression(self, &stmt.condition),
+            Self::visit_statement(self, &stmt.body)
+        )
+    }
+    fn visit_assign_expression(&self, expr: &AssignExpression) -> String {
+        format!(
+            "(= {} {})",
+            expr.name.lexeme,
+            Self::visit_expression(self, &expr.value)
+        )
+    }
     fn visit_binary_expression(&self, expr: &BinaryExpression) -> String {
         format!(
             "({} {} {})",
@@ -45,17 +187,70 @@ impl Visitor<String> for PrettyPrinter {
             Self::visit_expression(self, &expr.right),
         )
     }
+    fn visit_call_expression(&self, expr: &CallExpression) -> String {
+        let mut call = format!("(call {}", Self::visit_expression(self, &expr.callee));
+        for argument in &expr.arguments {
+            call.push(' ');
+            call.push_str(&Self::visit_expression(self, argument));
+        }
+        call.push(')');
+        call
+    }
+    fn visit_comma_expression(&self, expr: &CommaExpression) -> String {
+        format!(
+            "(, {} {})",
+            Self::visit_expression(self, &expr.left),
+            Self::visit_expression(self, &expr.right),
+        )
+    }
+    fn visit_conditional_expression(&self, expr: &ConditionalExpression) -> String {
+        format!(
+            "(?: {} {} {})",
+            Self::visit_expression(self, &expr.condition),
+            Self::visit_expression(self, &expr.then_branch),
+            Self::visit_expression(self, &expr.else_branch),
+        )
+    }
+    fn visit_get_expression(&self, expr: &GetExpression) -> String {
+        format!(
+            "(. {} {})",
+            Self::visit_expression(self, &expr.object),
+            expr.name.lexeme
+        )
+    }
     fn visit_grouping_expression(&self, expr: &GroupingExpression) -> String {
         format!("({})", Self::visit_expression(self, &expr.0))
     }
     fn visit_literal_expression(&self, expr: &LiteralExpression) -> String {
         match expr {
-            LiteralExpression::Bool(token) => token.lexeme.clone(),
-            LiteralExpression::Nil(token) => token.lexeme.clone(),
-            LiteralExpression::Number(token) => token.lexeme.clone(),
-            LiteralExpression::String(token) => token.lexeme.clone(),
+            LiteralExpression::Bool(token) => token.lexeme.to_string(),
+            LiteralExpression::Nil(token) => token.lexeme.to_string(),
+            LiteralExpression::Number(token) => token.lexeme.to_string(),
+            LiteralExpression::String(token) => token.lexeme.to_string(),
         }
     }
+    fn visit_logical_expression(&self, expr: &LogicalExpression) -> String {
+        format!(
+            "({} {} {})",
+            expr.operator.lexeme,
+            Self::visit_expression(self, &expr.left),
+            Self::visit_expression(self, &expr.right),
+        )
+    }
+    fn visit_set_expression(&self, expr: &SetExpression) -> String {
+        format!(
+            "(= (. {} {}) {})",
+            Self::visit_expression(self, &expr.object),
+            expr.name.lexeme,
+            Self::visit_expression(self, &expr.value)
+        )
+    }
+    fn visit_super_expression(&self, expr: &SuperExpression) -> String {
+        format!("(super {})", expr.method.lexeme)
+    }
+    fn visit_this_expression(&self, expr: &ThisExpression) -> String {
+        expr.keyword.lexeme.to_string()
+    }
     fn visit_unary_expression(&self, expr: &UnaryExpression) -> String {
         format!(
             "({} {})",
@@ -63,6 +258,9 @@ impl Visitor<String> for PrettyPrinter {
             Self::visit_expression(self, &expr.operand)
         )
     }
+    fn visit_variable_expression(&self, expr: &VariableExpression) -> String {
+        expr.name.lexeme.to_string()
+    }
 }
 
 pub struct GraphGenerator {
@@ -78,8 +276,8 @@ impl GraphGenerator {
         }
     }
 
-    pub fn generate_tree(&self, expr: &Expression) -> std::io::Result<()> {
-        self.visit_expression(expr);
+    pub fn generate_tree(&self, statements: &[Stmt]) -> std::io::Result<()> {
+        self.add_statements_node("program", statements);
 
         let graph_svg = exec(
             self.graph.borrow_mut().clone(),
@@ -92,10 +290,148 @@ impl GraphGenerator {
 
         Ok(())
     }
+
+    // add a node with the given label and link it
+    // to the root of the expression visited right before it
+    fn add_parent_node(&self, label: &str) {
+        let statement_id = *self.sequence.borrow();
+        let expression_id = statement_id - 1;
+
+        let statement_node =
+            node!(statement_id.to_string();attr!("label",(format!("\"{}\"", label))));
+
+        self.graph
+            .borrow_mut()
+            .add_stmt(DotStmt::Node(statement_node));
+        self.graph.borrow_mut().add_stmt(DotStmt::Edge(
+            edge!(node_id!(statement_id) => node_id!(expression_id)),
+        ));
+        *self.sequence.borrow_mut() += 1;
+    }
+
+    // visit all the statements and group them
+    // together under a node with the given label
+    fn add_statements_node(&self, label: &str, statements: &[Stmt]) {
+        let mut statement_ids = Vec::new();
+        for stmt in statements {
+            self.visit_statement(stmt);
+            statement_ids.push(*self.sequence.borrow() - 1);
+        }
+
+        self.add_node_with_children(label, &statement_ids);
+    }
+
+    // add a node with the given label and link it to
+    // the nodes with the given ids, in the given order
+    fn add_node_with_children(&self, label: &str, children: &[u64]) {
+        let parent_id = *self.sequence.borrow();
+        let parent_node = node!(parent_id.to_string();attr!("label",(format!("\"{}\"", label))));
+        self.graph.borrow_mut().add_stmt(DotStmt::Node(parent_node));
+        for child_id in children {
+            self.graph.borrow_mut().add_stmt(DotStmt::Edge(
+                edge!(node_id!(parent_id) => node_id!(child_id)),
+            ));
+        }
+        *self.sequence.borrow_mut() += 1;
+    }
+
+    // add a node without children
+    fn add_leaf_node(&self, label: &str) {
+        let leaf_id = *self.sequence.borrow();
+        let leaf_node = node!(leaf_id.to_string();attr!("label",(format!("\"{}\"", label))));
+        self.graph.borrow_mut().add_stmt(DotStmt::Node(leaf_node));
+        *self.sequence.borrow_mut() += 1;
+    }
+}
+
+impl Default for GraphGenerator {
+    fn default() -> Self {
+        Self::new()
+    }
 }
 
 impl Visitor<()> for GraphGenerator {
-    fn visit_binary_expression(&self, expr: &BinaryExpression) -> () {
+    fn visit_block_statement(&self, stmt: &BlockStatement) {
+        self.add_statements_node("block", &stmt.0);
+    }
+
+    fn visit_class_statement(&self, stmt: &ClassStatement) {
+        let mut children = Vec::new();
+        for method in &stmt.methods {
+            self.visit_function_statement(method);
+            children.push(*self.sequence.borrow() - 1);
+        }
+        let label = match &stmt.superclass {
+            Some(superclass) => format!("class {} < {}", stmt.name.lexeme, superclass.name.lexeme),
+            None => format!("class {}", stmt.name.lexeme),
+        };
+        self.add_node_with_children(&label, &children);
+    }
+
+    fn visit_expression_statement(&self, stmt: &ExpressionStatement) {
+        self.visit_expression(&stmt.0);
+        self.add_parent_node(";");
+    }
+
+    fn visit_function_statement(&self, stmt: &Rc<FunctionStatement>) {
+        let params: Vec<String> = stmt.params.iter().map(|p| p.lexeme.to_string()).collect();
+        let label = format!("fun {}({})", stmt.name.lexeme, params.join(", "));
+        self.add_statements_node(&label, &stmt.body);
+    }
+
+    fn visit_if_statement(&self, stmt: &IfStatement) {
+        let mut children = Vec::new();
+        self.visit_expression(&stmt.condition);
+        children.push(*self.sequence.borrow() - 1);
+        self.visit_statement(&stmt.then_branch);
+        children.push(*self.sequence.borrow() - 1);
+        if let Some(else_branch) = &stmt.else_branch {
+            self.visit_statement(else_branch);
+            children.push(*self.sequence.borrow() - 1);
+        }
+        self.add_node_with_children("if", &children);
+    }
+
+    fn visit_print_statement(&self, stmt: &PrintStatement) {
+        self.visit_expression(&stmt.0);
+        self.add_parent_node("print");
+    }
+
+    fn visit_return_statement(&self, stmt: &ReturnStatement) {
+        match &stmt.value {
+            Some(value) => {
+                self.visit_expression(value);
+                self.add_parent_node("return");
+            }
+            None => self.add_leaf_node("return"),
+        }
+    }
+
+    fn visit_var_statement(&self, stmt: &VarStatement) {
+        let label = format!("var {}", stmt.name.lexeme);
+        match &stmt.initializer {
+            Some(initializer) => {
+                self.visit_expression(initializer);
+                self.add_parent_node(&label);
+            }
+            None => self.add_leaf_node(&label),
+        }
+    }
+
+    fn visit_while_statement(&self, stmt: &WhileStatement) {
+        self.visit_expression(&stmt.condition);
+        let condition_id = *self.sequence.borrow() - 1;
+        self.visit_statement(&stmt.body);
+        let body_id = *self.sequence.borrow() - 1;
+        self.add_node_with_children("while", &[condition_id, body_id]);
+    }
+
+    fn visit_assign_expression(&self, expr: &AssignExpression) {
+        self.visit_expression(&expr.value);
+        self.add_parent_node(&format!("{} =", expr.name.lexeme));
+    }
+
+    fn visit_binary_expression(&self, expr: &BinaryExpression) {
         self.visit_expression(&expr.left);
 
         // the id of the previous lef
@@ -108,40 +444,99 @@ impl Visitor<()> for GraphGenerator {
         let operator_node =
             node!(operator_id.to_string();attr!("label",(format!("\"{}\"", expr.operator.lexeme))));
 
-        self.graph.borrow_mut().add_stmt(Stmt::Node(operator_node));
-        self.graph.borrow_mut().add_stmt(Stmt::Edge(
+        self.graph
+            .borrow_mut()
+            .add_stmt(DotStmt::Node(operator_node));
+        self.graph.borrow_mut().add_stmt(DotStmt::Edge(
             edge!(node_id!(operator_id) => node_id!(left_node_id)),
         ));
-        self.graph.borrow_mut().add_stmt(Stmt::Edge(
+        self.graph.borrow_mut().add_stmt(DotStmt::Edge(
             edge!(node_id!(operator_id) => node_id!(right_node_id)),
         ));
 
         *self.sequence.borrow_mut() += 1;
     }
 
-    fn visit_grouping_expression(&self, expr: &GroupingExpression) -> () {
+    fn visit_call_expression(&self, expr: &CallExpression) {
+        let mut children = Vec::new();
+        self.visit_expression(&expr.callee);
+        children.push(*self.sequence.borrow() - 1);
+        for argument in &expr.arguments {
+            self.visit_expression(argument);
+            children.push(*self.sequence.borrow() - 1);
+        }
+        self.add_node_with_children("call", &children);
+    }
+
+    fn visit_comma_expression(&self, expr: &CommaExpression) {
+        self.visit_expression(&expr.left);
+        let left_node_id = *self.sequence.borrow() - 1;
+        self.visit_expression(&expr.right);
+        let right_node_id = *self.sequence.borrow() - 1;
+        self.add_node_with_children(",", &[left_node_id, right_node_id]);
+    }
+
+    fn visit_conditional_expression(&self, expr: &ConditionalExpression) {
+        let mut children = Vec::new();
+        for operand in [&expr.condition, &expr.then_branch, &expr.else_branch] {
+            self.visit_expression(operand);
+            children.push(*self.sequence.borrow() - 1);
+        }
+        self.add_node_with_children("?:", &children);
+    }
+
+    fn visit_get_expression(&self, expr: &GetExpression) {
+        self.visit_expression(&expr.object);
+        self.add_parent_node(&format!(".{}", expr.name.lexeme));
+    }
+
+    fn visit_grouping_expression(&self, expr: &GroupingExpression) {
         self.visit_expression(&expr.0);
     }
 
-    fn visit_literal_expression(&self, expr: &LiteralExpression) -> () {
+    fn visit_literal_expression(&self, expr: &LiteralExpression) {
         let literal_id = *self.sequence.borrow();
-        let literal_node: Node;
-        match expr {
+        let literal_node: Node = match expr {
             LiteralExpression::Bool(token)
             | LiteralExpression::Nil(token)
             | LiteralExpression::Number(token) => {
-                literal_node =
-                    node!(literal_id.to_string();attr!("label",(format!("\"{}\"", token.lexeme))));
+                node!(literal_id.to_string();attr!("label",(format!("\"{}\"", token.lexeme))))
             }
             LiteralExpression::String(token) => {
-                literal_node = node!(literal_id.to_string();attr!("label",(format!("\"'{}'\"", token.lexeme.trim_matches('"')))));
+                node!(literal_id.to_string();attr!("label",(format!("\"'{}'\"", token.lexeme.trim_matches('"')))))
             }
-        }
-        self.graph.borrow_mut().add_stmt(Stmt::Node(literal_node));
+        };
+        self.graph
+            .borrow_mut()
+            .add_stmt(DotStmt::Node(literal_node));
         *self.sequence.borrow_mut() += 1;
     }
 
-    fn visit_unary_expression(&self, expr: &UnaryExpression) -> () {
+    fn visit_logical_expression(&self, expr: &LogicalExpression) {
+        self.visit_expression(&expr.left);
+        let left_node_id = *self.sequence.borrow() - 1;
+        self.visit_expression(&expr.right);
+        let right_node_id = *self.sequence.borrow() - 1;
+        self.add_node_with_children(&expr.operator.lexeme, &[left_node_id, right_node_id]);
+    }
+
+    fn visit_set_expression(&self, expr: &SetExpression) {
+        self.visit_expression(&expr.object);
+        let object_id = *self.sequence.borrow() - 1;
+        self.visit_expression(&expr.value);
+        let value_id = *self.sequence.borrow() - 1;
+        self.add_node_with_children(&format!(".{} =", expr.name.lexeme), &[object_id, value_id]);
+    }
+
+    fn visit_super_expression(&self, expr: &SuperExpression) {
+        self.add_leaf_node(&format!("super.{}", expr.method.lexeme));
+    }
+
+    fn visit_this_expression(&self, expr: &ThisExpression) {
+        self.add_leaf_node(&expr.keyword.lexeme);
+    }
+
+    fn visit_unary_expression(&self, expr: &UnaryExpression) {
         self.visit_expression(&expr.operand);
         let operator_id = *self.sequence.borrow();
         let expression_id = operator_id - 1;
@@ -149,10 +544,16 @@ impl Visitor<()> for GraphGenerator {
         let operator_node =
             node!(operator_id.to_string();attr!("label",(format!("\"{}\"", expr.operator.lexeme))));
 
-        self.graph.borrow_mut().add_stmt(Stmt::Node(operator_node));
-        self.graph.borrow_mut().add_stmt(Stmt::Edge(
+        self.graph
+            .borrow_mut()
+            .add_stmt(DotStmt::Node(operator_node));
+        self.graph.borrow_mut().add_stmt(DotStmt::Edge(
             edge!(node_id!(operator_id) => node_id!(expression_id)),
         ));
         *self.sequence.borrow_mut() += 1;
     }
+
+    fn visit_variable_expression(&self, expr: &VariableExpression) {
+        self.add_leaf_node(&expr.name.lexeme);
+    }
 }
@@ -0,0 +1,480 @@
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::interner::Symbol;
+use crate::parser::ast::*;
+use crate::parser::visitor::Visitor;
+use crate::token::Token;
+
+// For each local variable expression, the number of scopes
+// between the expression and the scope where the variable is declared.
+// Variables that don't appear here are globals.
+pub type Locals = HashMap<ExpressionId, usize>;
+
+pub struct ResolveError {
+    pub message: String,
+    pub token: Token,
+    // a hint on how to fix the error
+    pub help: Option<String>,
+}
+
+// run the resolver over the whole program and return the
+// scope depth of its local variables or all the static errors
+pub fn resolve(statements: &[Stmt]) -> Result<Locals, Vec<ResolveError>> {
+    let resolver = Resolver::new();
+    for stmt in statements {
+        resolver.visit_statement(stmt);
+    }
+
+    let errors = resolver.errors.into_inner();
+    if errors.is_empty() {
+        Ok(resolver.locals.into_inner())
+    } else {
+        Err(errors)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionType {
+    None,
+    Function,
+    Initializer,
+    Method,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum ClassType {
+    None,
+    Class,
+    Subclass,
+}
+
+// The resolver is a static pass that walks the syntax tree
+// before the execution, binding each variable to the scope
+// that declares it and reporting the errors that can
+// be detected without running the code
+struct Resolver {
+    // the stack of the local scopes currently in scope.
+    // Each maps a variable to whether its initializer has been resolved.
+    // The global scope is not tracked.
+    scopes: RefCell<Vec<HashMap<Symbol, bool>>>,
+    current_function: Cell<FunctionType>,
+    current_class: Cell<ClassType>,
+    locals: RefCell<Locals>,
+    errors: RefCell<Vec<ResolveError>>,
+}
+
+impl Resolver {
+    fn new() -> Self {
+        Resolver {
+            scopes: RefCell::new(Vec::new()),
+            current_function: Cell::new(FunctionType::None),
+            current_class: Cell::new(ClassType::None),
+            locals: RefCell::new(HashMap::new()),
+            errors: RefCell::new(Vec::new()),
+        }
+    }
+
+    fn error(&self, token: &Token, message: &str) {
+        self.errors.borrow_mut().push(ResolveError {
+            message: message.to_string(),
+            token: token.clone(),
+            help: None,
+        })
+    }
+
+    fn error_with_help(&self, token: &Token, message: &str, help: &str) {
+        self.errors.borrow_mut().push(ResolveError {
+            message: message.to_string(),
+            token: token.clone(),
+            help: Some(help.to_string()),
+        })
+    }
+
+    fn begin_scope(&self) {
+        self.scopes.borrow_mut().push(HashMap::new());
+    }
+
+    fn end_scope(&self) {
+        self.scopes.borrow_mut().pop();
+    }
+
+    // add the variable to the innermost scope, marking it as
+    // "not ready yet" until its initializer has been resolved
+    fn declare(&self, name: &Token) {
+        let mut scopes = self.scopes.borrow_mut();
+        if let Some(scope) = scopes.last_mut() {
+            if scope.contains_key(&name.name()) {
+                drop(scopes);
+                self.error(name, "already a variable with this name in this scope");
+                return;
+            }
+            scope.insert(name.name(), false);
+        }
+    }
+
+    fn define(&self, name: &Token) {
+        if let Some(scope) = self.scopes.borrow_mut().last_mut() {
+            scope.insert(name.name(), true);
+        }
+    }
+
+    // look for the variable starting from the innermost scope.
+    // If it's not found in any scope, it's assumed to be a global
+    fn resolve_local(&self, id: ExpressionId, name: &Token) {
+        let scopes = self.scopes.borrow();
+        for (depth, scope) in scopes.iter().rev().enumerate() {
+            if scope.contains_key(&name.name()) {
+                self.locals.borrow_mut().insert(id, depth);
+                return;
+            }
+        }
+    }
+
+    fn resolve_function(&self, function: &FunctionStatement, r#type: FunctionType) {
+        let enclosing_function = self.current_function.replace(r#type);
+
+        self.begin_scope();
+        for param in &function.params {
+            self.declare(param);
+            self.define(param);
+        }
+        for stmt in &function.body {
+            self.visit_statement(stmt);
+        }
+        self.end_scope();
+
+        self.current_function.set(enclosing_function);
+    }
+}
+
+impl Visitor<()> for Resolver {
+    fn visit_block_statement(&self, stmt: &BlockStatement) {
+        self.begin_scope();
+        for statement in &stmt.0 {
+            self.visit_statement(statement);
+        }
+        self.end_scope();
+    }
+
+    fn visit_class_statement(&self, stmt: &ClassStatement) {
+        let enclosing_class = self.current_class.replace(ClassType::Class);
+        self.declare(&stmt.name);
+        self.define(&stmt.name);
+
+        // the superclass methods are reachable with "super"
+        // from a scope that encloses the one with "this"
+        if let Some(superclass) = &stmt.superclass {
+            if superclass.name.name() == stmt.name.name() {
+                self.error(&superclass.name, "a class can't inherit from itself");
+            }
+            self.current_class.set(ClassType::Subclass);
+            self.visit_variable_expression(superclass);
+
+            self.begin_scope();
+            if let Some(scope) = self.scopes.borrow_mut().last_mut() {
+                scope.insert(Symbol::SUPER, true);
+            }
+        }
+
+        // the methods are enclosed in a scope where "this"
+        // is bound to the instance the method is accessed from
+        self.begin_scope();
+        if let Some(scope) = self.scopes.borrow_mut().last_mut() {
+            scope.insert(Symbol::THIS, true);
+        }
+        for method in &stmt.methods {
+            let r#type = if method.name.name() == Symbol::INIT {
+                FunctionType::Initializer
+            } else {
+                FunctionType::Method
+            };
+            self.resolve_function(method, r#type);
+        }
+        self.end_scope();
+        if stmt.superclass.is_some() {
+            self.end_scope();
+        }
+
+        self.current_class.set(enclosing_class);
+    }
+
+    fn visit_expression_statement(&self, stmt: &ExpressionStatement) {
+        self.visit_expression(&stmt.0);
+    }
+
+    fn visit_function_statement(&self, stmt: &Rc<FunctionStatement>) {
+        // the name is defined eagerly so that
+        // the function can refer to itself recursively
+        self.declare(&stmt.name);
+        self.define(&stmt.name);
+        self.resolve_function(stmt, FunctionType::Function);
+    }
+
+    fn visit_if_statement(&self, stmt: &IfStatement) {
+        self.visit_expression(&stmt.condition);
+        self.visit_statement(&stmt.then_branch);
+        if let Some(else_branch) = &stmt.else_branch {
+            self.visit_statement(else_branch);
+        }
+    }
+
+    fn visit_print_statement(&self, stmt: &PrintStatement) {
+        self.visit_expression(&stmt.0);
+    }
+
+    fn visit_return_statement(&self, stmt: &ReturnStatement) {
+        if self.current_function.get() == FunctionType::None {
+            self.error_with_help(
+                &stmt.keyword,
+                "can't return from top-level code",
+                "'return' can only be used inside a function or a method",
+            );
+        }
+        if let Some(value) = &stmt.value {
+            if self.current_function.get() == FunctionType::Initializer {
+                self.error_with_help(
+                    &stmt.keyword,
+                    "can't return a value from an initializer",
+                    "initializers always return the instance, use 'return;' to exit early",
+                );
+            }
+            self.visit_expression(value);
+        }
+    }
+
+    fn visit_var_statement(&self, stmt: &VarStatement) {
+        self.declare(&stmt.name);
+        if let Some(initializer) = &stmt.initializer {
+            self.visit_expression(initializer);
+        }
+        self.define(&stmt.name);
+    }
+
+    fn visit_while_statement(&self, stmt: &WhileStatement) {
+        self.visit_expression(&stmt.condition);
+        self.visit_statement(&stmt.body);
+    }
+
+    fn visit_assign_expression(&self, expr: &AssignExpression) {
+        self.visit_expression(&expr.value);
+        self.resolve_local(expr.id, &expr.name);
+    }
+
+    fn visit_binary_expression(&self, expr: &BinaryExpression) {
+        self.visit_expression(&expr.left);
+        self.visit_expression(&expr.right);
+    }
+
+    fn visit_call_expression(&self, expr: &CallExpression) {
+        self.visit_expression(&expr.callee);
+        for argument in &expr.arguments {
+            self.visit_expression(argument);
+        }
+    }
+
+    fn visit_comma_expression(&self, expr: &CommaExpression) {
+        self.visit_expression(&expr.left);
+        self.visit_expression(&expr.right);
+    }
+
+    fn visit_conditional_expression(&self, expr: &ConditionalExpression) {
+        self.visit_expression(&expr.condition);
+        self.visit_expression(&expr.then_branch);
+        self.visit_expression(&expr.else_branch);
+    }
+
+    fn visit_get_expression(&self, expr: &GetExpression) {
+        // properties are looked up dynamically, so only the object is resolved
+        self.visit_expression(&expr.object);
+    }
+
+    fn visit_grouping_expression(&self, expr: &GroupingExpression) {
+        self.visit_expression(&expr.0);
+    }
+
+    fn visit_literal_expression(&self, _expr: &LiteralExpression) {}
+
+    fn visit_logical_expression(&self, expr: &LogicalExpression) {
+        self.visit_expression(&expr.left);
+        self.visit_expression(&expr.right);
+    }
+
+    fn visit_set_expression(&self, expr: &SetExpression) {
+        self.visit_expression(&expr.value);
+        self.visit_expression(&expr.object);
+    }
+
+    fn visit_super_expression(&self, expr: &SuperExpression) {
+        match self.current_class.get() {
+            ClassType::None => self.error(&expr.keyword, "can't use 'super' outside of a class"),
+            ClassType::Class => self.error_with_help(
+                &expr.keyword,
+                "can't use 'super' in a class with no superclass",
+                "declare a superclass with 'class Name < Superclass'",
+            ),
+            ClassType::Subclass => self.resolve_local(expr.id, &expr.keyword),
+        }
+    }
+
+    fn visit_this_expression(&self, expr: &ThisExpression) {
+        if self.current_class.get() == ClassType::None {
+            self.error_with_help(
+                &expr.keyword,
+                "can't use 'this' outside of a class",
+                "'this' refers to the instance a method is called on",
+            );
+            return;
+        }
+        self.resolve_local(expr.id, &expr.keyword);
+    }
+
+    fn visit_unary_expression(&self, expr: &UnaryExpression) {
+        self.visit_expression(&expr.operand);
+    }
+
+    fn visit_variable_expression(&self, expr: &VariableExpression) {
+        if let Some(scope) = self.scopes.borrow().last() {
+            if scope.get(&expr.name.name()) == Some(&false) {
+                self.error(
+                    &expr.name,
+                    "can't read local variable in its own initializer",
+                );
+            }
+        }
+        self.resolve_local(expr.id, &expr.name);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+    use crate::scanner::Scanner;
+
+    fn resolve_source(source: &str) -> Result<Locals, Vec<ResolveError>> {
+        let mut scanner = Scanner::new(source);
+        let (tokens, errors) = scanner.scan_tokens();
+        assert!(errors.is_empty(), "unexpected lexical errors");
+        let statements = parser::parse(tokens)
+            .ok()
+            .expect("unexpected syntax errors");
+        resolve(&statements)
+    }
+
+    // the line and the message of each static error in the source
+    fn resolve_errors(source: &str) -> Vec<(u64, String)> {
+        match resolve_source(source) {
+            Ok(_) => Vec::new(),
+            Err(errors) => errors
+                .into_iter()
+                .map(|error| (error.token.line, error.message))
+                .collect(),
+        }
+    }
+
+    // the depth of the local variables, in the order they appear in the source
+    fn depths(source: &str) -> Vec<usize> {
+        let Ok(locals) = resolve_source(source) else {
+            panic!("unexpected static errors");
+        };
+        let mut locals: Vec<_> = locals.into_iter().collect();
+        locals.sort();
+        locals.into_iter().map(|(_, depth)| depth).collect()
+    }
+
+    #[test]
+    fn resolves_local_depths() {
+        // the globals are left out
+        assert_eq!(
+            depths("var a = 1;\n{ var b = a;\n { print b;\n fun f() { return b; } } }"),
+            [1, 2]
+        );
+        assert_eq!(depths("fun f(x) { x = 1; { return x; } }"), [0, 1]);
+        assert_eq!(depths("class A < B { m() { this; super.m(); } }"), [1, 2]);
+    }
+
+    #[test]
+    fn reading_a_local_in_its_own_initializer() {
+        assert_eq!(
+            resolve_errors("var a = 1;\n{ var a = a; }"),
+            [(
+                2,
+                "can't read local variable in its own initializer".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn redeclaring_in_the_same_scope() {
+        assert_eq!(
+            resolve_errors("{\n var a = 1;\n var a = 2;\n}"),
+            [(
+                3,
+                "already a variable with this name in this scope".to_string()
+            )]
+        );
+        assert_eq!(
+            resolve_errors("fun f(a, a) {}"),
+            [(
+                1,
+                "already a variable with this name in this scope".to_string()
+            )]
+        );
+        // the globals can be redeclared
+        assert!(resolve_errors("var a = 1; var a = 2;").is_empty());
+    }
+
+    #[test]
+    fn returning_from_top_level_code() {
+        assert_eq!(
+            resolve_errors("print 1;\nreturn;"),
+            [(2, "can't return from top-level code".to_string())]
+        );
+    }
+
+    #[test]
+    fn returning_a_value_from_an_initializer() {
+        assert_eq!(
+            resolve_errors("class A {\n init() {\n return 1;\n }\n}"),
+            [(3, "can't return a value from an initializer".to_string())]
+        );
+        assert!(resolve_errors("class A { init() { return; } }").is_empty());
+    }
+
+    #[test]
+    fn this_outside_of_a_class() {
+        assert_eq!(
+            resolve_errors("print this;"),
+            [(1, "can't use 'this' outside of a class".to_string())]
+        );
+        assert_eq!(
+            resolve_errors("fun f() {\n return this;\n}"),
+            [(2, "can't use 'this' outside of a class".to_string())]
+        );
+    }
+
+    #[test]
+    fn super_outside_of_a_subclass() {
+        assert_eq!(
+            resolve_errors("super.m();"),
+            [(1, "can't use 'super' outside of a class".to_string())]
+        );
+        assert_eq!(
+            resolve_errors("class A {\n m() { super.m(); }\n}"),
+            [(
+                2,
+                "can't use 'super' in a class with no superclass".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn inheriting_from_itself() {
+        assert_eq!(
+            resolve_errors("class A < A {}"),
+            [(1, "a class can't inherit from itself".to_string())]
+        );
+    }
+}
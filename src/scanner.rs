@@ -1,18 +1,34 @@
 use std::{
     collections::HashMap,
+    rc::Rc,
     str::{Chars, FromStr},
 };
 
 use multipeek::{multipeek, MultiPeek};
 
-use crate::token::{Token, TokenType};
+use crate::interner::Symbol;
+use crate::token::{Span, Token, TokenType};
+
+// A lexeme that doesn't form a valid token
+pub struct LexicalError {
+    pub message: String,
+    pub token: Token,
+}
 
 pub struct Scanner<'a> {
     source: MultiPeek<Chars<'a>>,
     tokens: Vec<Token>,
     current_lexeme_buf: Vec<char>,
-    current: u64,
+    // byte offset of the first char of the current lexeme
+    start: usize,
+    // byte offset of the next char to scan
+    current: usize,
     line: u64,
+    // column of the next char to scan
+    column: u64,
+    // line and column where the current lexeme begins
+    start_line: u64,
+    start_column: u64,
     keywords: HashMap<String, TokenType>,
 }
 
@@ -22,8 +38,12 @@ impl<'a> Scanner<'a> {
             source: multipeek(source.chars()),
             tokens: Vec::new(),
             current_lexeme_buf: Vec::new(),
+            start: 0,
             current: 0,
             line: 1,
+            column: 1,
+            start_line: 1,
+            start_column: 1,
             keywords: HashMap::from([
                 ("and".into(), TokenType::And),
                 ("class".into(), TokenType::Class),
@@ -44,21 +64,51 @@ impl<'a> Scanner<'a> {
             ]),
         }
     }
+
+    // Scan a source that follows another one, like a line of the REPL
+    // after the previous ones, so that the positions of the tokens are
+    // in the whole text. The source must begin at the start of a line
+    pub fn continuing(mut self, offset: usize, line: u64) -> Self {
+        self.start = offset;
+        self.current = offset;
+        self.line = line;
+        self.start_line = line;
+        self
+    }
 }
 
 impl<'a> Scanner<'a> {
-    pub fn scan_tokens(&mut self) -> Vec<Token> {
+    // Scan the whole source. The invalid lexemes are scanned as
+    // SyntaxErr tokens, which are taken out of the token stream and
+    // returned as lexical errors, so that the parser can still go on
+    // and report the syntax errors of the rest of the source.
+    pub fn scan_tokens(&mut self) -> (Vec<Token>, Vec<LexicalError>) {
         while !self.is_at_end() {
+            // we are at the beginning of the next lexeme
+            self.start = self.current;
+            self.start_line = self.line;
+            self.start_column = self.column;
             self.scan_token()
         }
 
-        self.tokens.push(Token {
-            r#type: TokenType::EOF,
-            lexeme: "".to_string(),
-            line: self.line,
-        });
+        self.start = self.current;
+        self.start_line = self.line;
+        self.start_column = self.column;
+        let eof = self.make_token(TokenType::EOF, "");
+        self.tokens.push(eof);
 
-        return std::mem::take(&mut self.tokens);
+        let mut tokens = Vec::new();
+        let mut errors = Vec::new();
+        for token in std::mem::take(&mut self.tokens) {
+            match &token.r#type {
+                TokenType::SyntaxErr(message) => errors.push(LexicalError {
+                    message: message.clone(),
+                    token,
+                }),
+                _ => tokens.push(token),
+            }
+        }
+        (tokens, errors)
     }
 
     fn peek(&mut self) -> Option<&char> {
@@ -75,7 +125,17 @@ impl<'a> Scanner<'a> {
 
     fn advance(&mut self) -> Option<char> {
         let char = self.source.next()?;
-        self.current += 1;
+        // offsets are in bytes, so that they can be used
+        // to slice the source, while columns are in chars
+        self.current += char.len_utf8();
+        // new lines are counted here, so that lexemes spanning
+        // multiple lines, like strings, keep the position right
+        if char == '\n' {
+            self.line += 1;
+            self.column = 1;
+        } else {
+            self.column += 1;
+        }
         self.current_lexeme_buf.push(char);
         Some(char)
     }
@@ -88,45 +148,257 @@ impl<'a> Scanner<'a> {
         false
     }
 
-    fn consume_digits(&mut self) {
-        loop {
-            if let Some(c) = self.peek() {
-                if c.is_ascii_digit() {
+    // Consume a run of digits in the given radix, appending them
+    // to `digits` without the "_" separators. A separator is only
+    // allowed between two digits, otherwise the error is returned.
+    fn consume_digits(&mut self, radix: u32, digits: &mut String) -> Result<(), String> {
+        let mut error = None;
+        let mut after_separator = false;
+        while let Some(&c) = self.peek() {
+            if c == '_' {
+                if error.is_none() && digits.is_empty() {
+                    error = Some("a digit separator must follow a digit");
+                } else if error.is_none() && after_separator {
+                    error = Some("consecutive digit separators");
+                }
+                after_separator = true;
+            } else if c.is_digit(radix) {
+                digits.push(c);
+                after_separator = false;
+            } else {
+                break;
+            }
+            self.advance();
+        }
+        if error.is_none() && after_separator {
+            error = Some("trailing digit separator");
+        }
+        match error {
+            Some(message) => Err(message.to_string()),
+            None => Ok(()),
+        }
+    }
+
+    // Scan a number literal, whose first digit has already been consumed:
+    // - decimals, with an optional fraction and exponent: 12, 1.5, 2.5E3, 1e-9
+    // - hexadecimals, binaries and octals: 0xff, 0b101, 0o17
+    // The digits can be grouped with "_" separators, e.g. 1_000_000.
+    // A trailing "." is not part of the number, so that `1.` is
+    // scanned as the number 1 followed by a dot.
+    fn scan_number(&mut self, first: char) {
+        let radix = match (first, self.peek()) {
+            ('0', Some('x')) => Some((16, "hexadecimal")),
+            ('0', Some('b')) => Some((2, "binary")),
+            ('0', Some('o')) => Some((8, "octal")),
+            _ => None,
+        };
+        let r#type = match radix {
+            Some((radix, name)) => {
+                // consume the prefix
+                let letter = self.advance().unwrap();
+                self.scan_integer(radix, name, &format!("{}{}", first, letter))
+            }
+            None => self.scan_decimal(first),
+        };
+        let r#type = r#type.unwrap_or_else(TokenType::SyntaxErr);
+        self.add_token(r#type);
+    }
+
+    fn scan_integer(&mut self, radix: u32, name: &str, prefix: &str) -> Result<TokenType, String> {
+        let mut digits = String::new();
+        let separators = self.consume_digits(radix, &mut digits);
+
+        // the digits that don't belong to the radix, e.g. 0b12, are
+        // consumed with the literal so that the error covers all of it
+        if let Some(&c) = self.peek() {
+            if Self::is_alphanumeric(&c) {
+                while self.peek().is_some_and(Self::is_alphanumeric) {
                     self.advance();
-                    continue;
                 }
+                return Err(format!("invalid digit '{}' in {} literal", c, name));
             }
-            break;
         }
+        if digits.is_empty() {
+            return Err(format!("missing digits after the '{}' prefix", prefix));
+        }
+        separators?;
+
+        // f64 and not an integer type, so that the
+        // big literals lose precision instead of overflowing
+        let value = digits
+            .chars()
+            .filter_map(|digit| digit.to_digit(radix))
+            .fold(0.0, |value, digit| value * radix as f64 + digit as f64);
+        Ok(TokenType::Number(value))
     }
 
-    fn scan_number(&mut self) {
-        self.consume_digits();
+    fn scan_decimal(&mut self, first: char) -> Result<TokenType, String> {
+        let mut digits = String::from(first);
+        let mut separators = self.consume_digits(10, &mut digits);
 
-        if Some(&'.') == self.peek() {
-            if let Some(c) = self.peek_nth(1) {
-                if c.is_ascii_digit() {
-                    // consume the "."
+        if Some(&'.') == self.peek() && self.peek_nth(1).is_some_and(char::is_ascii_digit) {
+            // consume the "."
+            self.advance();
+            digits.push('.');
+            let mut fraction = String::new();
+            // consume the remaining digits
+            let result = self.consume_digits(10, &mut fraction);
+            separators = separators.and(result);
+            digits.push_str(&fraction);
+        }
+
+        if let Some(&exponent @ ('e' | 'E')) = self.peek() {
+            self.advance();
+            digits.push(exponent);
+            if let Some(&sign @ ('+' | '-')) = self.peek() {
+                self.advance();
+                digits.push(sign);
+            }
+            let mut exponent = String::new();
+            let result = self.consume_digits(10, &mut exponent);
+            if exponent.is_empty() {
+                return Err("missing digits in the exponent".to_string());
+            }
+            separators = separators.and(result);
+            digits.push_str(&exponent);
+        }
+        separators?;
+
+        // parse the number from the digits, without the separators
+        f64::from_str(&digits)
+            .map(TokenType::Number)
+            .map_err(|_| "Failed to parse number".to_string())
+    }
+
+    // Scan a string literal, which can span multiple lines.
+    // The value of the token is the content between
+    // the quotes, with the escape sequences replaced.
+    fn scan_string(&mut self) {
+        let mut literal = String::new();
+        loop {
+            match self.peek() {
+                None => {
+                    // the error points to the opening quote only,
+                    // instead of the whole rest of the source
+                    let mut token = self.make_token(
+                        TokenType::SyntaxErr("Unterminated string".to_string()),
+                        "\"",
+                    );
+                    token.span.end = token.span.start + 1;
+                    self.tokens.push(token);
+                    self.current_lexeme_buf.clear();
+                    return;
+                }
+                Some('"') => break,
+                Some('\\') => {
+                    let start = (self.current, self.line, self.column);
+                    let buf_start = self.current_lexeme_buf.len();
+                    // eat the backslash
                     self.advance();
-                    // consume the remaining digits
-                    self.consume_digits();
+                    match self.scan_escape() {
+                        Some(Ok(c)) => literal.push(c),
+                        Some(Err(message)) => {
+                            // the error only covers the escape
+                            // sequence, the string goes on
+                            let lexeme = String::from_iter(&self.current_lexeme_buf[buf_start..]);
+                            let (offset, line, column) = start;
+                            self.tokens.push(Token {
+                                r#type: TokenType::SyntaxErr(message),
+                                lexeme: Rc::from(lexeme),
+                                line,
+                                column,
+                                span: Span {
+                                    start: offset,
+                                    end: self.current,
+                                },
+                            });
+                        }
+                        // the string ends right after the backslash
+                        None => continue,
+                    }
                 }
+                Some(_) => literal.push(self.advance().unwrap()),
             }
         }
-        let lexeme = String::from_iter(self.current_lexeme_buf.drain(..));
-        // parse the number from the lexeme (string)
-        match f64::from_str(lexeme.as_str()) {
-            Ok(f) => self.tokens.push(Token {
-                r#type: TokenType::Number(f),
-                lexeme,
-                line: self.line,
-            }),
-            Err(_) => self.tokens.push(Token {
-                r#type: TokenType::SyntaxErr("Failed to parse number".to_string()),
-                lexeme,
-                line: self.line,
-            }),
+        // eat the closing ".
+        self.advance();
+        self.add_token(TokenType::String(Symbol::intern(&literal)));
+    }
+
+    // Scan the escape sequence following a backslash:
+    // \n, \t, \", \\ or \u{XXXX} with 1 to 6 hex digits.
+    // Returns None if the source ends before the sequence
+    fn scan_escape(&mut self) -> Option<Result<char, String>> {
+        let c = *self.peek()?;
+        let escaped = match c {
+            'n' => '\n',
+            't' => '\t',
+            '"' => '"',
+            '\\' => '\\',
+            'u' => {
+                self.advance();
+                return Some(self.scan_unicode_escape());
+            }
+            // the new line is not part of
+            // the escape, it's left to the string
+            '\n' | '\r' => {
+                return Some(Err(
+                    "line continuation is not supported in strings".to_string()
+                ));
+            }
+            _ => {
+                self.advance();
+                return Some(Err(format!("unknown escape sequence '\\{}'", c)));
+            }
+        };
+        self.advance();
+        Some(Ok(escaped))
+    }
+
+    fn scan_unicode_escape(&mut self) -> Result<char, String> {
+        let malformed = || "malformed unicode escape, expected '\\u{XXXX}'".to_string();
+        if !self.advance_on_match('{') {
+            return Err(malformed());
+        }
+        let mut digits = String::new();
+        while let Some(c) = self.peek() {
+            if !c.is_ascii_hexdigit() {
+                break;
+            }
+            digits.push(self.advance().unwrap());
+        }
+        if digits.is_empty() || digits.len() > 6 || !self.advance_on_match('}') {
+            return Err(malformed());
+        }
+        u32::from_str_radix(&digits, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(|| format!("invalid unicode code point '{}' in escape", digits))
+    }
+
+    // Skip a /* ... */ comment. Block comments can be nested,
+    // so each opening "/*" must be matched by its own "*/"
+    fn scan_block_comment(&mut self) {
+        let mut depth = 1;
+        while depth > 0 {
+            match self.advance() {
+                Some('/') if self.advance_on_match('*') => depth += 1,
+                Some('*') if self.advance_on_match('/') => depth -= 1,
+                Some(_) => {}
+                None => {
+                    // the error points to the opening "/*" only,
+                    // instead of the whole rest of the source
+                    let mut token = self.make_token(
+                        TokenType::SyntaxErr("Unterminated block comment".to_string()),
+                        "/*",
+                    );
+                    token.span.end = token.span.start + 2;
+                    self.tokens.push(token);
+                    break;
+                }
+            }
         }
+        self.current_lexeme_buf.clear();
     }
 
     // keep advancing the scanner until the next
@@ -143,22 +415,37 @@ impl<'a> Scanner<'a> {
         }
     }
 
-    // Matches [A-Z][a-z] and "_"
+    // Matches the chars that can start an identifier:
+    // "_" and the Unicode XID_Start chars, e.g. [A-Z][a-z], "é" or "名"
     fn is_alpha(c: &char) -> bool {
-        c.is_ascii_alphabetic() || c == &'_'
+        c == &'_' || unicode_ident::is_xid_start(*c)
     }
 
-    // Matches [A-Z][a-z][0-9] and "_"
+    // Matches the chars that can continue an identifier: the Unicode
+    // XID_Continue chars, which include [0-9], "_" and all of XID_Start
     fn is_alphanumeric(c: &char) -> bool {
-        Self::is_alpha(c) || c.is_ascii_digit()
+        unicode_ident::is_xid_continue(*c)
     }
 
-    fn add_token(&mut self, r#type: TokenType) {
-        self.tokens.push(Token {
+    // build a token spanning from the start of
+    // the current lexeme to the current position
+    fn make_token(&self, r#type: TokenType, lexeme: &str) -> Token {
+        Token {
             r#type,
-            lexeme: String::from_iter(self.current_lexeme_buf.drain(..)),
-            line: self.line,
-        })
+            lexeme: Rc::from(lexeme),
+            line: self.start_line,
+            column: self.start_column,
+            span: Span {
+                start: self.start,
+                end: self.current,
+            },
+        }
+    }
+
+    fn add_token(&mut self, r#type: TokenType) {
+        let lexeme = String::from_iter(self.current_lexeme_buf.drain(..));
+        let token = self.make_token(r#type, &lexeme);
+        self.tokens.push(token)
     }
 
     fn scan_identifier_or_keyword(&mut self) {
@@ -172,21 +459,15 @@ impl<'a> Scanner<'a> {
             break;
         }
         let lexeme = String::from_iter(self.current_lexeme_buf.drain(..));
-        match self.keywords.get(&lexeme) {
+        let r#type = match self.keywords.get(&lexeme) {
             // if the map contains this lexeme, then
             // it's a reserved keyword
-            Some(token_type) => self.tokens.push(Token {
-                r#type: token_type.clone(),
-                lexeme,
-                line: self.line,
-            }),
+            Some(token_type) => token_type.clone(),
             // otherwise it's just an identifier
-            None => self.tokens.push(Token {
-                r#type: TokenType::Identifier,
-                lexeme,
-                line: self.line,
-            }),
-        }
+            None => TokenType::Identifier(Symbol::intern(&lexeme)),
+        };
+        let token = self.make_token(r#type, &lexeme);
+        self.tokens.push(token);
     }
 
     fn scan_token(&mut self) {
@@ -201,6 +482,8 @@ impl<'a> Scanner<'a> {
             '-' => self.add_token(TokenType::Minus),
             '+' => self.add_token(TokenType::Plus),
             ';' => self.add_token(TokenType::Semicolon),
+            '?' => self.add_token(TokenType::Question),
+            ':' => self.add_token(TokenType::Colon),
             '/' => {
                 if self.advance_on_match('/') {
                     // in this case it's a comment line
@@ -208,6 +491,8 @@ impl<'a> Scanner<'a> {
                     // new line char '\n'
                     self.advance_until('\n');
                     self.current_lexeme_buf.clear();
+                } else if self.advance_on_match('*') {
+                    self.scan_block_comment();
                 } else {
                     self.add_token(TokenType::Slash)
                 }
@@ -241,31 +526,221 @@ impl<'a> Scanner<'a> {
                     self.add_token(TokenType::Less)
                 }
             }
-            '"' => {
-                self.advance_until('"');
-                if self.is_at_end() {
-                    self.add_token(TokenType::SyntaxErr("Unterminated string".to_string()))
-                }
-                // eat the closing ".
-                self.advance();
-
-                let lexeme = String::from_iter(self.current_lexeme_buf.drain(..));
-                // trim the surrounding quotes
-                let literal = lexeme.trim_matches('"').to_string();
-                self.tokens.push(Token {
-                    r#type: TokenType::String(literal),
-                    lexeme,
-                    line: self.line,
-                })
-            }
-            ' ' | '\r' | '\t' => self.current_lexeme_buf.clear(), // clear and move ahead.
-            '\n' => {
-                self.current_lexeme_buf.clear();
-                self.line += 1
-            }
-            d if d.is_ascii_digit() => self.scan_number(),
+            '"' => self.scan_string(),
+            // clear and move ahead, new lines are counted by advance()
+            ' ' | '\r' | '\t' | '\n' => self.current_lexeme_buf.clear(),
+            d if d.is_ascii_digit() => self.scan_number(d),
             a if Self::is_alpha(&a) => self.scan_identifier_or_keyword(),
             _ => self.add_token(TokenType::SyntaxErr("Invalid character".to_string())),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the types of the tokens scanned from the source, without the EOF
+    fn scan(source: &str) -> Vec<TokenType> {
+        let (mut tokens, errors) = Scanner::new(source).scan_tokens();
+        assert!(errors.is_empty(), "unexpected lexical errors");
+        tokens.pop();
+        tokens.into_iter().map(|token| token.r#type).collect()
+    }
+
+    fn scan_error(source: &str) -> String {
+        let (_, errors) = Scanner::new(source).scan_tokens();
+        assert_eq!(errors.len(), 1, "expected exactly one lexical error");
+        errors[0].message.clone()
+    }
+
+    #[test]
+    fn continuing_source_positions() {
+        let (tokens, _) = Scanner::new("print x;\n").continuing(12, 3).scan_tokens();
+        assert_eq!((tokens[1].line, tokens[1].column), (3, 7));
+        assert_eq!(tokens[1].span, Span { start: 18, end: 19 });
+        assert_eq!(tokens.last().unwrap().line, 4);
+    }
+
+    #[test]
+    fn decimal_numbers() {
+        assert_eq!(scan("0"), vec![TokenType::Number(0.0)]);
+        assert_eq!(scan("123"), vec![TokenType::Number(123.0)]);
+        assert_eq!(scan("123.45"), vec![TokenType::Number(123.45)]);
+        assert_eq!(scan("007"), vec![TokenType::Number(7.0)]);
+    }
+
+    #[test]
+    fn trailing_dot_is_not_part_of_the_number() {
+        assert_eq!(scan("1."), vec![TokenType::Number(1.0), TokenType::Dot]);
+        assert_eq!(
+            scan("1.foo"),
+            vec![
+                TokenType::Number(1.0),
+                TokenType::Dot,
+                TokenType::Identifier(Symbol::intern("foo"))
+            ]
+        );
+    }
+
+    #[test]
+    fn leading_dot_is_not_part_of_the_number() {
+        assert_eq!(scan(".5"), vec![TokenType::Dot, TokenType::Number(5.0)]);
+    }
+
+    #[test]
+    fn prefixed_numbers() {
+        assert_eq!(scan("0xff"), vec![TokenType::Number(255.0)]);
+        assert_eq!(scan("0xDEAD"), vec![TokenType::Number(57005.0)]);
+        assert_eq!(scan("0b1010"), vec![TokenType::Number(10.0)]);
+        assert_eq!(scan("0o17"), vec![TokenType::Number(15.0)]);
+    }
+
+    #[test]
+    fn exponents() {
+        assert_eq!(scan("1e-9"), vec![TokenType::Number(1e-9)]);
+        assert_eq!(scan("2.5E3"), vec![TokenType::Number(2500.0)]);
+        assert_eq!(scan("1e+2"), vec![TokenType::Number(100.0)]);
+    }
+
+    #[test]
+    fn digit_separators() {
+        assert_eq!(scan("1_000_000"), vec![TokenType::Number(1_000_000.0)]);
+        assert_eq!(scan("0xff_ff"), vec![TokenType::Number(65535.0)]);
+        assert_eq!(scan("1_0.2_5e1_0"), vec![TokenType::Number(10.25e10)]);
+    }
+
+    #[test]
+    fn malformed_numbers() {
+        assert_eq!(scan_error("0x"), "missing digits after the '0x' prefix");
+        assert_eq!(scan_error("0b"), "missing digits after the '0b' prefix");
+        assert_eq!(scan_error("0x_"), "missing digits after the '0x' prefix");
+        assert_eq!(scan_error("1e"), "missing digits in the exponent");
+        assert_eq!(scan_error("1.5e-"), "missing digits in the exponent");
+        assert_eq!(scan_error("1__0"), "consecutive digit separators");
+        assert_eq!(scan_error("1_"), "trailing digit separator");
+        assert_eq!(scan_error("1_.5"), "trailing digit separator");
+        assert_eq!(scan_error("0x_1"), "a digit separator must follow a digit");
+        assert_eq!(scan_error("0b102"), "invalid digit '2' in binary literal");
+        assert_eq!(scan_error("0o8"), "invalid digit '8' in octal literal");
+        assert_eq!(
+            scan_error("0xfg"),
+            "invalid digit 'g' in hexadecimal literal"
+        );
+    }
+
+    #[test]
+    fn escape_sequences() {
+        assert_eq!(
+            scan(r#""a\nb\t\"\\""#),
+            vec![TokenType::String(Symbol::intern("a\nb\t\"\\"))]
+        );
+        assert_eq!(
+            scan(r#""\u{41}\u{1F600}\u{0}""#),
+            vec![TokenType::String(Symbol::intern("A\u{1F600}\0"))]
+        );
+    }
+
+    #[test]
+    fn malformed_escape_sequences() {
+        assert_eq!(scan_error(r#""\q""#), "unknown escape sequence '\\q'");
+        assert_eq!(
+            scan_error("\"a\\\nb\""),
+            "line continuation is not supported in strings"
+        );
+        let malformed = "malformed unicode escape, expected '\\u{XXXX}'";
+        assert_eq!(scan_error(r#""\u41""#), malformed);
+        assert_eq!(scan_error(r#""\u{}""#), malformed);
+        assert_eq!(scan_error(r#""\u{41""#), malformed);
+        assert_eq!(scan_error(r#""\u{1000000}""#), malformed);
+        assert_eq!(
+            scan_error(r#""\u{110000}""#),
+            "invalid unicode code point '110000' in escape"
+        );
+        assert_eq!(
+            scan_error(r#""\u{D800}""#),
+            "invalid unicode code point 'D800' in escape"
+        );
+    }
+
+    #[test]
+    fn escape_error_spans_the_sequence() {
+        let (tokens, errors) = Scanner::new(r#"print "a\qb";"#).scan_tokens();
+        assert_eq!(errors[0].token.span, Span { start: 8, end: 10 });
+        // the string goes on after the error
+        assert_eq!(tokens[1].r#type, TokenType::String(Symbol::intern("ab")));
+    }
+
+    #[test]
+    fn multi_line_strings() {
+        let (tokens, _) = Scanner::new("\"a\nb\" x\n\"\\n\" y").scan_tokens();
+        assert_eq!(tokens[0].r#type, TokenType::String(Symbol::intern("a\nb")));
+        assert_eq!((tokens[0].line, tokens[0].column), (1, 1));
+        assert_eq!((tokens[1].line, tokens[1].column), (2, 4));
+        // an escaped new line doesn't start a line
+        assert_eq!((tokens[3].line, tokens[3].column), (3, 6));
+    }
+
+    #[test]
+    fn unterminated_string() {
+        assert_eq!(scan_error("\"abc"), "Unterminated string");
+        let (_, errors) = Scanner::new("1 \"a\n b").scan_tokens();
+        assert_eq!(errors[0].token.span, Span { start: 2, end: 3 });
+        assert_eq!(errors[0].token.line, 1);
+    }
+
+    #[test]
+    fn block_comments() {
+        assert_eq!(scan("/* a */ 1"), vec![TokenType::Number(1.0)]);
+        assert_eq!(
+            scan("/* a /* nested */ still a comment */ 1"),
+            vec![TokenType::Number(1.0)]
+        );
+        assert_eq!(
+            scan("1 /**/ 2"),
+            vec![TokenType::Number(1.0), TokenType::Number(2.0)]
+        );
+    }
+
+    #[test]
+    fn unterminated_block_comments() {
+        assert_eq!(scan_error("/* a"), "Unterminated block comment");
+        assert_eq!(scan_error("/* a /* b */"), "Unterminated block comment");
+        let (_, errors) = Scanner::new("1 /* a\n b").scan_tokens();
+        assert_eq!(errors[0].token.span, Span { start: 2, end: 4 });
+        assert_eq!(errors[0].token.line, 1);
+    }
+
+    #[test]
+    fn lines_after_comments() {
+        let (tokens, _) = Scanner::new("/* a\nb\n*/ x // c\ny").scan_tokens();
+        assert_eq!((tokens[0].line, tokens[0].column), (3, 4));
+        assert_eq!((tokens[1].line, tokens[1].column), (4, 1));
+    }
+
+    #[test]
+    fn unicode_identifiers() {
+        let names = ["café", "变量", "_x1", "ñ2"];
+        assert_eq!(
+            scan(&names.join(" ")),
+            names.map(|name| TokenType::Identifier(Symbol::intern(name)))
+        );
+    }
+
+    #[test]
+    fn columns_after_multi_byte_chars() {
+        let (tokens, _) = Scanner::new("\"é\" 变量 x").scan_tokens();
+        // the columns count chars, the spans bytes
+        assert_eq!(tokens[1].column, 5);
+        assert_eq!(tokens[1].span, Span { start: 5, end: 11 });
+        assert_eq!(tokens[2].column, 8);
+        assert_eq!(tokens[2].span, Span { start: 12, end: 13 });
+    }
+
+    #[test]
+    fn malformed_number_spans_the_whole_literal() {
+        let (_, errors) = Scanner::new("print 0b102;").scan_tokens();
+        assert_eq!(&*errors[0].token.lexeme, "0b102");
+        assert_eq!(errors[0].token.span, Span { start: 6, end: 11 });
+    }
+}
@@ -1,7 +1,10 @@
 use std::fmt::Display;
+use std::rc::Rc;
 
 use strum_macros::EnumDiscriminants;
 
+use crate::interner::Symbol;
+
 #[derive(Debug, Clone, PartialEq, EnumDiscriminants)]
 #[strum_discriminants(name(TokenDiscriminant))]
 pub enum TokenType {
@@ -17,6 +20,8 @@ pub enum TokenType {
     Semicolon,
     Slash,
     Star,
+    Question,
+    Colon,
 
     // One or two character tokens.
     Bang,
@@ -29,8 +34,10 @@ pub enum TokenType {
     LessEqual,
 
     // Literals
-    Identifier,
-    String(String),
+    // the name, interned since the names are looked up
+    Identifier(Symbol),
+    // the content of the string, already unescaped
+    String(Symbol),
     Number(f64),
 
     // Keywords
@@ -57,25 +64,68 @@ pub enum TokenType {
     EOF,
 }
 
+// The region of the source a token was scanned from,
+// as byte offsets of its first char and of the char right after its last one
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+// Where a token is in the source. It's kept in place of the token
+// by what outlives the syntax tree, like errors and bytecode
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Location {
+    pub line: u64,
+    pub column: u64,
+    pub span: Span,
+}
+
 #[derive(Debug, Clone)]
 pub struct Token {
     pub r#type: TokenType,
-    pub lexeme: String,
+    // the text of the token in the source
+    pub lexeme: Rc<str>,
+    // the line and the column (in chars, starting from 1)
+    // where the token begins
     pub line: u64,
+    pub column: u64,
+    pub span: Span,
 }
 
 impl Token {
     pub fn discriminant(&self) -> TokenDiscriminant {
         (&self.r#type).into()
     }
+
+    // The name of an identifier, or of the keywords bound like
+    // variables. Only the names are looked up, in the environments
+    // or among the fields and methods
+    pub fn name(&self) -> Symbol {
+        match self.r#type {
+            TokenType::Identifier(name) => name,
+            TokenType::This => Symbol::THIS,
+            TokenType::Super => Symbol::SUPER,
+            _ => unreachable!("only names are looked up"),
+        }
+    }
+
+    pub fn location(&self) -> Location {
+        Location {
+            line: self.line,
+            column: self.column,
+            span: self.span,
+        }
+    }
 }
 
 impl Display for Token {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         write!(
             f,
-            "Line: {} - {:?} {}",
+            "Line: {}:{} - {:?} {}",
             self.line,
+            self.column,
             self.discriminant(),
             self.lexeme
         )
@@ -0,0 +1,183 @@
+use super::value::Value;
+use crate::interner::Symbol;
+use crate::token::Location;
+
+// The instructions of the VM. The operands are stored inline:
+// indexes into the constant pool, stack slots, upvalue indexes,
+// jump offsets, argument counts and the names of the globals,
+// properties and methods
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OpCode {
+    Constant(u32),
+    Nil,
+    True,
+    False,
+    Pop,
+    GetLocal(u32),
+    SetLocal(u32),
+    DefineGlobal(Symbol),
+    GetGlobal(Symbol),
+    SetGlobal(Symbol),
+    GetUpvalue(u32),
+    SetUpvalue(u32),
+    GetProperty(Symbol),
+    SetProperty(Symbol),
+    GetSuper(Symbol),
+    Equal,
+    NotEqual,
+    Greater,
+    GreaterEqual,
+    Less,
+    LessEqual,
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Not,
+    Negate,
+    Print,
+    // the jumps are relative to the next instruction
+    Jump(u32),
+    JumpIfFalse(u32),
+    Loop(u32),
+    Call(u32),
+    Closure(u32),
+    CloseUpvalue,
+    Return,
+    Class(Symbol),
+    Inherit,
+    Method(Symbol),
+}
+
+impl OpCode {
+    // whether the instruction can raise a runtime error, which
+    // is reported at the exact location of the instruction
+    pub fn can_fail(self) -> bool {
+        matches!(
+            self,
+            OpCode::GetGlobal(_)
+                | OpCode::SetGlobal(_)
+                | OpCode::GetProperty(_)
+                | OpCode::SetProperty(_)
+                | OpCode::GetSuper(_)
+                | OpCode::Greater
+                | OpCode::GreaterEqual
+                | OpCode::Less
+                | OpCode::LessEqual
+                | OpCode::Add
+                | OpCode::Subtract
+                | OpCode::Multiply
+                | OpCode::Divide
+                | OpCode::Negate
+                | OpCode::Call(_)
+                | OpCode::Inherit
+        )
+    }
+}
+
+// The consecutive instructions compiled from the same line
+struct LineRun {
+    line: u64,
+    // the offset of the first instruction of the run
+    start: usize,
+}
+
+// A sequence of instructions with the constants they refer to
+#[derive(Default)]
+pub struct Chunk {
+    pub code: Vec<OpCode>,
+    pub constants: Vec<Value>,
+    // the source line of each instruction, run-length encoded,
+    // used by the stack traces and the disassembler
+    lines: Vec<LineRun>,
+    // the location of each instruction that can fail, used to
+    // report the runtime errors, in the order of the offsets
+    locations: Vec<(usize, Location)>,
+}
+
+impl Chunk {
+    // append the instruction and return its offset
+    pub fn write(&mut self, op: OpCode, location: Location) -> usize {
+        let offset = self.code.len();
+        self.code.push(op);
+        if self
+            .lines
+            .last()
+            .is_none_or(|run| run.line != location.line)
+        {
+            self.lines.push(LineRun {
+                line: location.line,
+                start: offset,
+            });
+        }
+        if op.can_fail() {
+            self.locations.push((offset, location));
+        }
+        offset
+    }
+
+    // add the value to the constant pool and return its index
+    pub fn add_constant(&mut self, value: Value) -> u32 {
+        self.constants.push(value);
+        (self.constants.len() - 1) as u32
+    }
+
+    // the source line of the instruction at the offset
+    pub fn line(&self, offset: usize) -> u64 {
+        let run = self.lines.partition_point(|run| run.start <= offset);
+        self.lines[run - 1].line
+    }
+
+    // the location of the instruction at the offset,
+    // which must be one of those that can fail
+    pub fn location(&self, offset: usize) -> Location {
+        let index = self
+            .locations
+            .binary_search_by_key(&offset, |(offset, _)| *offset)
+            .expect("only the instructions that can fail have a location");
+        self.locations[index].1
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::Span;
+
+    fn at(line: u64, column: u64) -> Location {
+        Location {
+            line,
+            column,
+            span: Span::default(),
+        }
+    }
+
+    #[test]
+    fn lines_are_run_length_encoded() {
+        let mut chunk = Chunk::default();
+        for (op, line) in [
+            (OpCode::Nil, 1),
+            (OpCode::Nil, 1),
+            (OpCode::Add, 1),
+            (OpCode::Print, 3),
+            (OpCode::Return, 4),
+        ] {
+            chunk.write(op, at(line, 1));
+        }
+        assert_eq!(chunk.lines.len(), 3);
+        let lines: Vec<u64> = (0..chunk.code.len())
+            .map(|offset| chunk.line(offset))
+            .collect();
+        assert_eq!(lines, [1, 1, 1, 3, 4]);
+    }
+
+    #[test]
+    fn only_the_failing_instructions_keep_the_location() {
+        let mut chunk = Chunk::default();
+        chunk.write(OpCode::Nil, at(1, 1));
+        chunk.write(OpCode::Negate, at(1, 7));
+        chunk.write(OpCode::Print, at(1, 1));
+        assert_eq!(chunk.locations.len(), 1);
+        assert_eq!(chunk.location(1), at(1, 7));
+    }
+}
@@ -0,0 +1,555 @@
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+use super::chunk::OpCode;
+use super::value::{Function, UpvalueDescriptor, Value};
+use crate::interner::Symbol;
+use crate::parser::ast::*;
+use crate::parser::visitor::Visitor;
+use crate::token::{Location, Token, TokenType};
+
+// Compile the program into the function run by the VM as the top-level
+// script. The program must have already been checked by the resolver,
+// which reports the static errors, so the compilation can't fail
+pub fn compile(statements: &[Stmt]) -> Rc<Function> {
+    let compiler = Compiler {
+        functions: RefCell::new(Vec::new()),
+        location: Cell::new(Location::default()),
+    };
+    compiler.begin_function("<script>", FunctionKind::Script);
+    for stmt in statements {
+        compiler.visit_statement(stmt);
+    }
+    Rc::new(compiler.end_function())
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum FunctionKind {
+    Script,
+    Function,
+    Initializer,
+    Method,
+}
+
+struct Local {
+    name: Symbol,
+    // the depth of the scope that declares the variable,
+    // None until its initializer has been compiled
+    depth: Option<usize>,
+    // whether a closure captured the variable, which then has to
+    // be moved off the stack when it goes out of scope
+    is_captured: bool,
+}
+
+// The state of a function being compiled
+struct FunctionState {
+    function: Function,
+    kind: FunctionKind,
+    // the local variables in scope, in the order of their stack slots
+    locals: Vec<Local>,
+    scope_depth: usize,
+}
+
+// A single-pass compiler from the syntax tree to bytecode.
+// The local variables are resolved to stack slots at compile
+// time, the same way the resolver binds them to scopes
+struct Compiler {
+    // the functions being compiled, from the outermost one
+    functions: RefCell<Vec<FunctionState>>,
+    // the location the emitted instructions are attributed to
+    location: Cell<Location>,
+}
+
+impl Compiler {
+    fn begin_function(&self, name: &str, kind: FunctionKind) {
+        // the first slot of each call frame holds the function being
+        // called, or the instance the method is called on
+        let slot_zero = match kind {
+            FunctionKind::Initializer | FunctionKind::Method => Symbol::THIS,
+            FunctionKind::Script | FunctionKind::Function => Symbol::intern(""),
+        };
+        self.functions.borrow_mut().push(FunctionState {
+            function: Function {
+                name: name.to_string(),
+                arity: 0,
+                chunk: Default::default(),
+                upvalues: Vec::new(),
+            },
+            kind,
+            locals: vec![Local {
+                name: slot_zero,
+                depth: Some(0),
+                is_captured: false,
+            }],
+            scope_depth: 0,
+        });
+    }
+
+    fn end_function(&self) -> Function {
+        self.emit_return();
+        self.functions.borrow_mut().pop().unwrap().function
+    }
+
+    // compile the function and emit the instruction that
+    // creates its closure at runtime
+    fn function(&self, declaration: &FunctionStatement, kind: FunctionKind) {
+        self.begin_function(&declaration.name.name().to_string(), kind);
+        self.begin_scope();
+        for param in &declaration.params {
+            self.declare_variable(param);
+            self.define_variable(param);
+        }
+        self.current().function.arity = declaration.params.len();
+        for stmt in &declaration.body {
+            self.visit_statement(stmt);
+        }
+        // no need to end the scope, returning discards the whole frame
+        let function = self.end_function();
+
+        let constant = self.make_constant(Value::Function(Rc::new(function)));
+        self.emit_at(OpCode::Closure(constant), &declaration.name);
+    }
+
+    fn current(&self) -> std::cell::RefMut<'_, FunctionState> {
+        std::cell::RefMut::map(self.functions.borrow_mut(), |functions| {
+            functions.last_mut().unwrap()
+        })
+    }
+
+    fn emit(&self, op: OpCode) -> usize {
+        let location = self.location.get();
+        self.current().function.chunk.write(op, location)
+    }
+
+    // emit the instruction attributing it to the token, so that
+    // the runtime errors it raises point to the token
+    fn emit_at(&self, op: OpCode, token: &Token) -> usize {
+        self.location.set(token.location());
+        self.emit(op)
+    }
+
+    fn emit_return(&self) {
+        // an initializer always returns the instance
+        if self.current().kind == FunctionKind::Initializer {
+            self.emit(OpCode::GetLocal(0));
+        } else {
+            self.emit(OpCode::Nil);
+        }
+        self.emit(OpCode::Return);
+    }
+
+    // emit a jump with a placeholder offset,
+    // to be patched once the target is known
+    fn emit_jump(&self, op: fn(u32) -> OpCode) -> usize {
+        self.emit(op(0))
+    }
+
+    // make the jump at the given offset land on the next instruction
+    fn patch_jump(&self, offset: usize) {
+        let mut current = self.current();
+        let code = &mut current.function.chunk.code;
+        let distance = (code.len() - offset - 1) as u32;
+        code[offset] = match code[offset] {
+            OpCode::Jump(_) => OpCode::Jump(distance),
+            OpCode::JumpIfFalse(_) => OpCode::JumpIfFalse(distance),
+            op => unreachable!("{:?} is not a forward jump", op),
+        };
+    }
+
+    fn emit_loop(&self, loop_start: usize) {
+        // the offset also skips the loop instruction itself
+        let distance = self.current().function.chunk.code.len() + 1 - loop_start;
+        self.emit(OpCode::Loop(distance as u32));
+    }
+
+    fn make_constant(&self, value: Value) -> u32 {
+        self.current().function.chunk.add_constant(value)
+    }
+
+    fn begin_scope(&self) {
+        self.current().scope_depth += 1;
+    }
+
+    // discard the variables of the scope, moving
+    // the captured ones off the stack
+    fn end_scope(&self) {
+        let mut current = self.current();
+        current.scope_depth -= 1;
+        let mut ops = Vec::new();
+        while let Some(local) = current.locals.last() {
+            if local
+                .depth
+                .is_some_and(|depth| depth <= current.scope_depth)
+            {
+                break;
+            }
+            ops.push(if local.is_captured {
+                OpCode::CloseUpvalue
+            } else {
+                OpCode::Pop
+            });
+            current.locals.pop();
+        }
+        drop(current);
+        for op in ops {
+            self.emit(op);
+        }
+    }
+
+    // local variables live in the stack slots, so declaring
+    // one only reserves the slot of its initializer's value
+    fn declare_variable(&self, name: &Token) {
+        let mut current = self.current();
+        if current.scope_depth > 0 {
+            current.locals.push(Local {
+                name: name.name(),
+                depth: None,
+                is_captured: false,
+            });
+        }
+    }
+
+    fn define_variable(&self, name: &Token) {
+        let mut current = self.current();
+        if current.scope_depth > 0 {
+            let depth = current.scope_depth;
+            current.locals.last_mut().unwrap().depth = Some(depth);
+            return;
+        }
+        drop(current);
+        self.emit_at(OpCode::DefineGlobal(name.name()), name);
+    }
+
+    // emit the instruction that reads or assigns the variable,
+    // depending on whether it's a local, captured or global one
+    fn named_variable(&self, name: &Token, assign: bool) {
+        let mut functions = self.functions.borrow_mut();
+        let current = functions.len() - 1;
+        let op = if let Some(slot) = resolve_local(&functions[current], name.name()) {
+            if assign {
+                OpCode::SetLocal(slot)
+            } else {
+                OpCode::GetLocal(slot)
+            }
+        } else if let Some(index) = resolve_upvalue(&mut functions, current, name.name()) {
+            if assign {
+                OpCode::SetUpvalue(index)
+            } else {
+                OpCode::GetUpvalue(index)
+            }
+        } else {
+            drop(functions);
+            let op = if assign {
+                OpCode::SetGlobal(name.name())
+            } else {
+                OpCode::GetGlobal(name.name())
+            };
+            self.emit_at(op, name);
+            return;
+        };
+        drop(functions);
+        self.emit_at(op, name);
+    }
+}
+
+fn resolve_local(function: &FunctionState, name: Symbol) -> Option<u32> {
+    function
+        .locals
+        .iter()
+        .rposition(|local| local.name == name)
+        .map(|slot| slot as u32)
+}
+
+// look for the variable in the enclosing functions, capturing
+// it in each function between the one declaring it and this one
+fn resolve_upvalue(functions: &mut [FunctionState], current: usize, name: Symbol) -> Option<u32> {
+    if current == 0 {
+        return None;
+    }
+    let enclosing = current - 1;
+    if let Some(slot) = resolve_local(&functions[enclosing], name) {
+        functions[enclosing].locals[slot as usize].is_captured = true;
+        return Some(add_upvalue(&mut functions[current], slot, true));
+    }
+    let index = resolve_upvalue(functions, enclosing, name)?;
+    Some(add_upvalue(&mut functions[current], index, false))
+}
+
+fn add_upvalue(function: &mut FunctionState, index: u32, is_local: bool) -> u32 {
+    let upvalues = &mut function.function.upvalues;
+    let existing = upvalues
+        .iter()
+        .position(|upvalue| upvalue.is_local == is_local && upvalue.index == index);
+    if let Some(existing) = existing {
+        return existing as u32;
+    }
+    upvalues.push(UpvalueDescriptor { is_local, index });
+    (upvalues.len() - 1) as u32
+}
+
+impl Visitor<()> for Compiler {
+    fn visit_block_statement(&self, stmt: &BlockStatement) {
+        self.begin_scope();
+        for statement in &stmt.0 {
+            self.visit_statement(statement);
+        }
+        self.end_scope();
+    }
+
+    fn visit_class_statement(&self, stmt: &ClassStatement) {
+        self.declare_variable(&stmt.name);
+        self.emit_at(OpCode::Class(stmt.name.name()), &stmt.name);
+        self.define_variable(&stmt.name);
+
+        // the superclass is kept in a local variable named "super",
+        // in a scope enclosing the methods, which capture it
+        if let Some(superclass) = &stmt.superclass {
+            self.visit_variable_expression(superclass);
+            self.begin_scope();
+            let mut current = self.current();
+            let depth = current.scope_depth;
+            current.locals.push(Local {
+                name: Symbol::SUPER,
+                depth: Some(depth),
+                is_captured: false,
+            });
+            drop(current);
+            self.named_variable(&stmt.name, false);
+            self.emit_at(OpCode::Inherit, &superclass.name);
+        }
+
+        // the class stays on the stack while its methods are added
+        self.named_variable(&stmt.name, false);
+        for method in &stmt.methods {
+            let kind = if method.name.name() == Symbol::INIT {
+                FunctionKind::Initializer
+            } else {
+                FunctionKind::Method
+            };
+            self.function(method, kind);
+            self.emit_at(OpCode::Method(method.name.name()), &method.name);
+        }
+        self.emit(OpCode::Pop);
+
+        if stmt.superclass.is_some() {
+            self.end_scope();
+        }
+    }
+
+    fn visit_expression_statement(&self, stmt: &ExpressionStatement) {
+        self.visit_expression(&stmt.0);
+        self.emit(OpCode::Pop);
+    }
+
+    fn visit_function_statement(&self, stmt: &Rc<FunctionStatement>) {
+        // the variable is initialized right away, so
+        // that the function can refer to itself recursively
+        self.declare_variable(&stmt.name);
+        if self.current().scope_depth > 0 {
+            self.define_variable(&stmt.name);
+            self.function(stmt, FunctionKind::Function);
+        } else {
+            self.function(stmt, FunctionKind::Function);
+            self.define_variable(&stmt.name);
+        }
+    }
+
+    fn visit_if_statement(&self, stmt: &IfStatement) {
+        self.visit_expression(&stmt.condition);
+        let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit(OpCode::Pop);
+        self.visit_statement(&stmt.then_branch);
+        let else_jump = self.emit_jump(OpCode::Jump);
+
+        self.patch_jump(then_jump);
+        self.emit(OpCode::Pop);
+        if let Some(else_branch) = &stmt.else_branch {
+            self.visit_statement(else_branch);
+        }
+        self.patch_jump(else_jump);
+    }
+
+    fn visit_print_statement(&self, stmt: &PrintStatement) {
+        self.visit_expression(&stmt.0);
+        self.emit(OpCode::Print);
+    }
+
+    fn visit_return_statement(&self, stmt: &ReturnStatement) {
+        self.location.set(stmt.keyword.location());
+        match &stmt.value {
+            Some(value) => {
+                self.visit_expression(value);
+                self.emit(OpCode::Return);
+            }
+            None => self.emit_return(),
+        }
+    }
+
+    fn visit_var_statement(&self, stmt: &VarStatement) {
+        self.declare_variable(&stmt.name);
+        // a variable declared without an initializer is nil
+        match &stmt.initializer {
+            Some(initializer) => self.visit_expression(initializer),
+            None => {
+                self.emit_at(OpCode::Nil, &stmt.name);
+            }
+        }
+        self.define_variable(&stmt.name);
+    }
+
+    fn visit_while_statement(&self, stmt: &WhileStatement) {
+        let loop_start = self.current().function.chunk.code.len();
+        self.visit_expression(&stmt.condition);
+        let exit_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit(OpCode::Pop);
+        self.visit_statement(&stmt.body);
+        self.emit_loop(loop_start);
+
+        self.patch_jump(exit_jump);
+        self.emit(OpCode::Pop);
+    }
+
+    fn visit_assign_expression(&self, expr: &AssignExpression) {
+        self.visit_expression(&expr.value);
+        self.named_variable(&expr.name, true);
+    }
+
+    fn visit_binary_expression(&self, expr: &BinaryExpression) {
+        self.visit_expression(&expr.left);
+        self.visit_expression(&expr.right);
+        let op = match expr.operator.r#type {
+            TokenType::Minus => OpCode::Subtract,
+            TokenType::Slash => OpCode::Divide,
+            TokenType::Star => OpCode::Multiply,
+            TokenType::Plus => OpCode::Add,
+            TokenType::Greater => OpCode::Greater,
+            TokenType::GreaterEqual => OpCode::GreaterEqual,
+            TokenType::Less => OpCode::Less,
+            TokenType::LessEqual => OpCode::LessEqual,
+            TokenType::EqualEqual => OpCode::Equal,
+            TokenType::BangEqual => OpCode::NotEqual,
+            _ => unreachable!("the parser only builds binary expressions with binary operators"),
+        };
+        self.emit_at(op, &expr.operator);
+    }
+
+    fn visit_call_expression(&self, expr: &CallExpression) {
+        self.visit_expression(&expr.callee);
+        for argument in &expr.arguments {
+            self.visit_expression(argument);
+        }
+        self.emit_at(OpCode::Call(expr.arguments.len() as u32), &expr.paren);
+    }
+
+    fn visit_comma_expression(&self, expr: &CommaExpression) {
+        self.visit_expression(&expr.left);
+        self.emit(OpCode::Pop);
+        self.visit_expression(&expr.right);
+    }
+
+    fn visit_conditional_expression(&self, expr: &ConditionalExpression) {
+        self.visit_expression(&expr.condition);
+        let then_jump = self.emit_jump(OpCode::JumpIfFalse);
+        self.emit(OpCode::Pop);
+        self.visit_expression(&expr.then_branch);
+        let else_jump = self.emit_jump(OpCode::Jump);
+
+        self.patch_jump(then_jump);
+        self.emit(OpCode::Pop);
+        self.visit_expression(&expr.else_branch);
+        self.patch_jump(else_jump);
+    }
+
+    fn visit_get_expression(&self, expr: &GetExpression) {
+        self.visit_expression(&expr.object);
+        self.emit_at(OpCode::GetProperty(expr.name.name()), &expr.name);
+    }
+
+    fn visit_grouping_expression(&self, expr: &GroupingExpression) {
+        self.visit_expression(&expr.0);
+    }
+
+    fn visit_literal_expression(&self, expr: &LiteralExpression) {
+        match expr {
+            LiteralExpression::Bool(token) => {
+                let op = if token.r#type == TokenType::True {
+                    OpCode::True
+                } else {
+                    OpCode::False
+                };
+                self.emit_at(op, token);
+            }
+            LiteralExpression::Nil(token) => {
+                self.emit_at(OpCode::Nil, token);
+            }
+            LiteralExpression::Number(token) => {
+                let TokenType::Number(number) = token.r#type else {
+                    unreachable!("number literals are built from number tokens")
+                };
+                let constant = self.make_constant(Value::Number(number));
+                self.emit_at(OpCode::Constant(constant), token);
+            }
+            LiteralExpression::String(token) => {
+                let TokenType::String(string) = &token.r#type else {
+                    unreachable!("string literals are built from string tokens")
+                };
+                let constant = self.make_constant(Value::String(string.string()));
+                self.emit_at(OpCode::Constant(constant), token);
+            }
+        }
+    }
+
+    // the right operand is skipped when the left one decides the result
+    fn visit_logical_expression(&self, expr: &LogicalExpression) {
+        self.visit_expression(&expr.left);
+        self.location.set(expr.operator.location());
+        if expr.operator.r#type == TokenType::And {
+            let end_jump = self.emit_jump(OpCode::JumpIfFalse);
+            self.emit(OpCode::Pop);
+            self.visit_expression(&expr.right);
+            self.patch_jump(end_jump);
+        } else {
+            let else_jump = self.emit_jump(OpCode::JumpIfFalse);
+            let end_jump = self.emit_jump(OpCode::Jump);
+            self.patch_jump(else_jump);
+            self.emit(OpCode::Pop);
+            self.visit_expression(&expr.right);
+            self.patch_jump(end_jump);
+        }
+    }
+
+    fn visit_set_expression(&self, expr: &SetExpression) {
+        self.visit_expression(&expr.object);
+        self.visit_expression(&expr.value);
+        self.emit_at(OpCode::SetProperty(expr.name.name()), &expr.name);
+    }
+
+    fn visit_super_expression(&self, expr: &SuperExpression) {
+        let this = Token {
+            r#type: TokenType::This,
+            lexeme: Rc::from("this"),
+            ..expr.keyword.clone()
+        };
+        self.named_variable(&this, false);
+        self.named_variable(&expr.keyword, false);
+        self.emit_at(OpCode::GetSuper(expr.method.name()), &expr.method);
+    }
+
+    fn visit_this_expression(&self, expr: &ThisExpression) {
+        self.named_variable(&expr.keyword, false);
+    }
+
+    fn visit_unary_expression(&self, expr: &UnaryExpression) {
+        self.visit_expression(&expr.operand);
+        let op = match expr.operator.r#type {
+            TokenType::Minus => OpCode::Negate,
+            TokenType::Bang => OpCode::Not,
+            _ => unreachable!("the parser only builds unary expressions with unary operators"),
+        };
+        self.emit_at(op, &expr.operator);
+    }
+
+    fn visit_variable_expression(&self, expr: &VariableExpression) {
+        self.named_variable(&expr.name, false);
+    }
+}
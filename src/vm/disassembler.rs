@@ -0,0 +1,142 @@
+use std::fmt::Write;
+
+use super::chunk::{Chunk, OpCode};
+use super::value::{Function, Value};
+
+// Render the bytecode of the function, followed by
+// the bytecode of the functions declared in it
+pub fn disassemble(function: &Function) -> String {
+    let mut output = format!("== {} ==\n", function.name);
+    for offset in 0..function.chunk.code.len() {
+        output.push_str(&disassemble_instruction(&function.chunk, offset));
+    }
+    for constant in &function.chunk.constants {
+        if let Value::Function(function) = constant {
+            output.push('\n');
+            output.push_str(&disassemble(function));
+        }
+    }
+    output
+}
+
+// Render a single instruction: its offset, the source line
+// ("|" when it's the same line as the previous instruction),
+// the opcode and its operands
+pub fn disassemble_instruction(chunk: &Chunk, offset: usize) -> String {
+    let mut output = format!("{:04} ", offset);
+    let line = chunk.line(offset);
+    if offset > 0 && chunk.line(offset - 1) == line {
+        output.push_str("   | ");
+    } else {
+        write!(output, "{:4} ", line).unwrap();
+    }
+
+    let op = chunk.code[offset];
+    // the name of the opcode, without the operand
+    let name = format!("{:?}", op);
+    let name = name.split('(').next().unwrap();
+    match op {
+        OpCode::Constant(index) => {
+            let constant = &chunk.constants[index as usize];
+            writeln!(output, "{:<16} {:4} '{}'", name, index, constant).unwrap();
+        }
+        // the names are aligned with the values of the constants
+        OpCode::DefineGlobal(symbol)
+        | OpCode::GetGlobal(symbol)
+        | OpCode::SetGlobal(symbol)
+        | OpCode::GetProperty(symbol)
+        | OpCode::SetProperty(symbol)
+        | OpCode::GetSuper(symbol)
+        | OpCode::Class(symbol)
+        | OpCode::Method(symbol) => {
+            writeln!(output, "{:<16} {:4} '{}'", name, "", symbol).unwrap();
+        }
+        OpCode::GetLocal(operand)
+        | OpCode::SetLocal(operand)
+        | OpCode::GetUpvalue(operand)
+        | OpCode::SetUpvalue(operand)
+        | OpCode::Call(operand) => {
+            writeln!(output, "{:<16} {:4}", name, operand).unwrap();
+        }
+        OpCode::Jump(distance) | OpCode::JumpIfFalse(distance) => {
+            let target = offset + 1 + distance as usize;
+            writeln!(output, "{:<16} {:4} -> {:04}", name, distance, target).unwrap();
+        }
+        OpCode::Loop(distance) => {
+            let target = offset + 1 - distance as usize;
+            writeln!(output, "{:<16} {:4} -> {:04}", name, distance, target).unwrap();
+        }
+        OpCode::Closure(index) => {
+            let constant = &chunk.constants[index as usize];
+            writeln!(output, "{:<16} {:4} '{}'", name, index, constant).unwrap();
+            // where each of the captured variables comes from
+            if let Value::Function(function) = constant {
+                for upvalue in &function.upvalues {
+                    let kind = if upvalue.is_local { "local" } else { "upvalue" };
+                    writeln!(
+                        output,
+                        "{:04}    | {:<16} {} {}",
+                        offset, "", kind, upvalue.index
+                    )
+                    .unwrap();
+                }
+            }
+        }
+        _ => writeln!(output, "{}", name).unwrap(),
+    }
+    output
+}
+
+// Render the values on the stack, from the bottom
+pub fn stack(stack: &[Value]) -> String {
+    let mut output = String::from("          ");
+    for value in stack {
+        write!(output, "[ {} ]", value).unwrap();
+    }
+    output.push('\n');
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::token::{Location, Span};
+
+    fn at(line: u64) -> Location {
+        Location {
+            line,
+            column: 1,
+            span: Span::default(),
+        }
+    }
+
+    #[test]
+    fn renders_offsets_lines_and_operands() {
+        let mut chunk = Chunk::default();
+        let index = chunk.add_constant(Value::Number(1.5));
+        for (op, line) in [
+            (OpCode::Constant(index), 1),
+            (OpCode::Print, 1),
+            (OpCode::JumpIfFalse(2), 2),
+            (OpCode::Nil, 2),
+            (OpCode::Pop, 3),
+            (OpCode::Loop(5), 3),
+            (OpCode::Return, 4),
+        ] {
+            chunk.write(op, at(line));
+        }
+        let output: String = (0..chunk.code.len())
+            .map(|offset| disassemble_instruction(&chunk, offset))
+            .collect();
+        assert_eq!(
+            output,
+            "0000    1 Constant            0 '1.5'\n\
+             0001    | Print\n\
+             0002    2 JumpIfFalse         2 -> 0005\n\
+             0003    | Nil\n\
+             0004    3 Pop\n\
+             0005    | Loop                5 -> 0001\n\
+             0006    4 Return\n"
+        );
+    }
+}
@@ -0,0 +1,8 @@
+mod chunk;
+mod compiler;
+mod disassembler;
+mod value;
+#[allow(clippy::module_inception)]
+mod vm;
+
+pub use vm::{Options, Vm};
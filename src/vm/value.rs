@@ -0,0 +1,196 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::rc::Rc;
+
+use super::chunk::Chunk;
+use crate::gc::{Trace, Tracer};
+use crate::interner::Symbol;
+use crate::natives::Native;
+
+// The values the VM works with. Unlike the tree walker ones,
+// they don't refer to the syntax tree, only to the compiled code
+#[derive(Clone)]
+pub enum Value {
+    Nil,
+    Bool(bool),
+    Number(f64),
+    // the strings of the literals are shared with the symbol table
+    String(Rc<str>),
+    // only found in the constant pool, the VM wraps it into a closure
+    Function(Rc<Function>),
+    Closure(Rc<Closure>),
+    Native(Native),
+    Class(Rc<Class>),
+    Instance(Rc<Instance>),
+    BoundMethod(Rc<BoundMethod>),
+}
+
+// A compiled function
+pub struct Function {
+    pub name: String,
+    pub arity: usize,
+    pub chunk: Chunk,
+    // where to find the variables captured by the closures of this
+    // function, in the order they are referred to by the upvalue opcodes
+    pub upvalues: Vec<UpvalueDescriptor>,
+}
+
+#[derive(Clone, Copy)]
+pub struct UpvalueDescriptor {
+    // whether the variable is a local of the enclosing function
+    // or one of the variables already captured by it
+    pub is_local: bool,
+    pub index: u32,
+}
+
+// A function together with the variables it captured
+pub struct Closure {
+    pub function: Rc<Function>,
+    pub upvalues: Vec<Rc<RefCell<Upvalue>>>,
+}
+
+impl Closure {
+    // closures are not tracked, the upvalues
+    // they capture are set after they are created
+    pub fn trace(&self, tracer: &mut Tracer) {
+        for upvalue in &self.upvalues {
+            tracer.mark(upvalue);
+        }
+    }
+}
+
+// A variable captured by a closure. It stays on the stack while the
+// scope that declared it is alive, and it's moved into the upvalue
+// when the scope ends, so that the closure can outlive it
+pub enum Upvalue {
+    Open(usize),
+    Closed(Value),
+}
+
+// an open upvalue refers to a stack slot, which is a root anyway
+impl Trace for RefCell<Upvalue> {
+    fn trace(&self, tracer: &mut Tracer) {
+        if let Upvalue::Closed(value) = &*self.borrow() {
+            value.trace(tracer);
+        }
+    }
+
+    fn clear(&self) {
+        *self.borrow_mut() = Upvalue::Closed(Value::Nil);
+    }
+}
+
+pub struct Class {
+    pub name: Symbol,
+    // the inherited methods are copied into the subclass
+    // when it's declared, so there's no chain to look up
+    pub methods: RefCell<HashMap<Symbol, Rc<Closure>>>,
+}
+
+impl Class {
+    pub fn find_method(&self, name: Symbol) -> Option<Rc<Closure>> {
+        self.methods.borrow().get(&name).cloned()
+    }
+}
+
+impl Trace for Class {
+    fn trace(&self, tracer: &mut Tracer) {
+        for method in self.methods.borrow().values() {
+            method.trace(tracer);
+        }
+    }
+
+    fn clear(&self) {
+        self.methods.borrow_mut().clear();
+    }
+}
+
+pub struct Instance {
+    pub class: Rc<Class>,
+    pub fields: RefCell<HashMap<Symbol, Value>>,
+}
+
+impl Trace for Instance {
+    fn trace(&self, tracer: &mut Tracer) {
+        tracer.mark(&self.class);
+        for value in self.fields.borrow().values() {
+            value.trace(tracer);
+        }
+    }
+
+    fn clear(&self) {
+        self.fields.borrow_mut().clear();
+    }
+}
+
+// A method accessed from an instance, which is bound to "this"
+pub struct BoundMethod {
+    pub receiver: Value,
+    pub method: Rc<Closure>,
+}
+
+// numbers, strings and booleans are compared by value,
+// all the other objects by identity
+impl PartialEq for Value {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Value::Nil, Value::Nil) => true,
+            (Value::Bool(left), Value::Bool(right)) => left == right,
+            (Value::Number(left), Value::Number(right)) => left == right,
+            (Value::String(left), Value::String(right)) => left == right,
+            (Value::Function(left), Value::Function(right)) => Rc::ptr_eq(left, right),
+            (Value::Closure(left), Value::Closure(right)) => Rc::ptr_eq(left, right),
+            (Value::Native(left), Value::Native(right)) => left == right,
+            (Value::Class(left), Value::Class(right)) => Rc::ptr_eq(left, right),
+            (Value::Instance(left), Value::Instance(right)) => Rc::ptr_eq(left, right),
+            (Value::BoundMethod(left), Value::BoundMethod(right)) => Rc::ptr_eq(left, right),
+            _ => false,
+        }
+    }
+}
+
+impl Value {
+    // false and nil are falsey, everything else is truthy,
+    // the same as LiteralValue::is_truthy
+    pub fn is_truthy(&self) -> bool {
+        match self {
+            Value::Nil => false,
+            Value::Bool(bool) => *bool,
+            _ => true,
+        }
+    }
+
+    // mark the tracked objects the value references. The functions
+    // only reference their constants, which are never tracked
+    pub fn trace(&self, tracer: &mut Tracer) {
+        match self {
+            Value::Closure(closure) => closure.trace(tracer),
+            Value::Class(class) => tracer.mark(class),
+            Value::Instance(instance) => tracer.mark(instance),
+            Value::BoundMethod(bound) => {
+                bound.receiver.trace(tracer);
+                bound.method.trace(tracer);
+            }
+            _ => {}
+        }
+    }
+}
+
+// the values are printed exactly as the tree walker prints them
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Nil => f.write_str("Nil"),
+            Value::Bool(bool) => write!(f, "{}", bool),
+            Value::Number(number) => write!(f, "{}", number),
+            Value::String(string) => write!(f, "{}", string),
+            Value::Function(function) => write!(f, "<fn {}>", function.name),
+            Value::Closure(closure) => write!(f, "<fn {}>", closure.function.name),
+            Value::Native(native) => write!(f, "<native fn {}>", native.name()),
+            Value::Class(class) => write!(f, "{}", class.name),
+            Value::Instance(instance) => write!(f, "{} instance", instance.class.name),
+            Value::BoundMethod(bound) => write!(f, "<fn {}>", bound.method.function.name),
+        }
+    }
+}
@@ -0,0 +1,561 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use super::chunk::OpCode;
+use super::compiler;
+use super::disassembler;
+use super::value::{BoundMethod, Class, Closure, Instance, Upvalue, Value};
+use crate::gc::{self, Heap};
+use crate::interner::Symbol;
+use crate::interpreter::{RuntimeError, StackFrame, StackTrace, FRAMES_MAX};
+use crate::natives::{Native, NativeValue};
+use crate::parser::ast::Stmt;
+
+// A function call in progress
+struct CallFrame {
+    closure: Rc<Closure>,
+    // the offset of the next instruction to execute
+    ip: usize,
+    // the stack slot of the callee, the first of the frame
+    base: usize,
+}
+
+// The debugging aids of the VM, printed on the standard output
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Options {
+    // print the bytecode of each program before running it
+    pub disassemble: bool,
+    // print the stack and the instruction before executing each instruction
+    pub trace_execution: bool,
+}
+
+// A stack-based virtual machine running the bytecode
+// compiled from the syntax tree
+pub struct Vm {
+    stack: Vec<Value>,
+    frames: Vec<CallFrame>,
+    // kept across the programs run by the same VM, so that
+    // global variables persist across the REPL lines
+    globals: HashMap<Symbol, Value>,
+    // the upvalues still pointing to a stack slot
+    open_upvalues: Vec<Rc<RefCell<Upvalue>>>,
+    options: Options,
+    heap: Rc<Heap>,
+}
+
+impl Vm {
+    pub fn new(options: Options, gc: gc::Options) -> Self {
+        let heap = Rc::new(Heap::new(gc));
+        let mut globals = HashMap::new();
+        for native in Native::ALL {
+            globals.insert(Symbol::intern(native.name()), Value::Native(native));
+        }
+
+        Vm {
+            stack: Vec::new(),
+            frames: Vec::new(),
+            globals,
+            open_upvalues: Vec::new(),
+            options,
+            heap,
+        }
+    }
+
+    pub fn interpret(&mut self, statements: &[Stmt]) -> Result<(), RuntimeError> {
+        let function = compiler::compile(statements);
+        if self.options.disassemble {
+            print!("{}", disassembler::disassemble(&function));
+        }
+        let closure = Rc::new(Closure {
+            function,
+            upvalues: Vec::new(),
+        });
+        self.stack.push(Value::Closure(Rc::clone(&closure)));
+        self.frames.push(CallFrame {
+            closure,
+            ip: 0,
+            base: 0,
+        });
+
+        let result = self.run();
+        if result.is_err() {
+            // start from a clean state, the globals are kept. The closures
+            // that escaped into a global still need the values they capture
+            self.close_upvalues(0);
+            self.stack.clear();
+            self.frames.clear();
+            self.open_upvalues.clear();
+        }
+        result
+    }
+
+    fn run(&mut self) -> Result<(), RuntimeError> {
+        loop {
+            // between two instructions all the values in use are on the
+            // stack, in the globals or in the upvalues, so it's safe to collect
+            if self.heap.collection_due() {
+                self.collect_garbage();
+            }
+            if self.options.trace_execution {
+                let frame = self.frames.last().unwrap();
+                print!("{}", disassembler::stack(&self.stack));
+                print!(
+                    "{}",
+                    disassembler::disassemble_instruction(&frame.closure.function.chunk, frame.ip)
+                );
+            }
+            let frame = self.frames.last_mut().unwrap();
+            let op = frame.closure.function.chunk.code[frame.ip];
+            frame.ip += 1;
+
+            match op {
+                OpCode::Constant(index) => {
+                    let constant = self.constant(index);
+                    self.stack.push(constant);
+                }
+                OpCode::Nil => self.stack.push(Value::Nil),
+                OpCode::True => self.stack.push(Value::Bool(true)),
+                OpCode::False => self.stack.push(Value::Bool(false)),
+                OpCode::Pop => {
+                    self.stack.pop();
+                }
+                OpCode::GetLocal(slot) => {
+                    let value = self.stack[self.base() + slot as usize].clone();
+                    self.stack.push(value);
+                }
+                OpCode::SetLocal(slot) => {
+                    // assignment is an expression, so the value stays on the stack
+                    let slot = self.base() + slot as usize;
+                    self.stack[slot] = self.peek(0).clone();
+                }
+                OpCode::DefineGlobal(name) => {
+                    let value = self.stack.pop().unwrap();
+                    self.globals.insert(name, value);
+                }
+                OpCode::GetGlobal(name) => match self.globals.get(&name) {
+                    Some(value) => self.stack.push(value.clone()),
+                    None => return Err(self.error(format!("undefined variable '{}'", name))),
+                },
+                OpCode::SetGlobal(name) => {
+                    let value = self.peek(0).clone();
+                    match self.globals.get_mut(&name) {
+                        Some(global) => *global = value,
+                        None => return Err(self.error(format!("undefined variable '{}'", name))),
+                    }
+                }
+                OpCode::GetUpvalue(index) => {
+                    let upvalue = self.upvalue(index);
+                    let value = match &*upvalue.borrow() {
+                        Upvalue::Open(slot) => self.stack[*slot].clone(),
+                        Upvalue::Closed(value) => value.clone(),
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::SetUpvalue(index) => {
+                    let upvalue = self.upvalue(index);
+                    let value = self.peek(0).clone();
+                    match &mut *upvalue.borrow_mut() {
+                        Upvalue::Open(slot) => self.stack[*slot] = value,
+                        Upvalue::Closed(closed) => *closed = value,
+                    };
+                }
+                OpCode::GetProperty(name) => {
+                    let Value::Instance(instance) = self.peek(0).clone() else {
+                        return Err(self.error(format!(
+                            "only instances have properties, can't read '{}'",
+                            name
+                        )));
+                    };
+                    // fields shadow the methods with the same name
+                    let field = instance.fields.borrow().get(&name).cloned();
+                    let value = match field {
+                        Some(value) => value,
+                        None => self.bind_method(&instance.class, name, self.peek(0).clone())?,
+                    };
+                    self.stack.pop();
+                    self.stack.push(value);
+                }
+                OpCode::SetProperty(name) => {
+                    let Value::Instance(instance) = self.peek(1).clone() else {
+                        return Err(
+                            self.error(format!("only instances have fields, can't set '{}'", name))
+                        );
+                    };
+                    let value = self.stack.pop().unwrap();
+                    instance.fields.borrow_mut().insert(name, value.clone());
+                    self.stack.pop();
+                    self.stack.push(value);
+                }
+                OpCode::GetSuper(name) => {
+                    let Some(Value::Class(superclass)) = self.stack.pop() else {
+                        unreachable!("'super' is always bound to a class");
+                    };
+                    let instance = self.stack.pop().unwrap();
+                    let method = self.bind_method(&superclass, name, instance)?;
+                    self.stack.push(method);
+                }
+                OpCode::Equal => {
+                    let (left, right) = self.pop_operands();
+                    self.stack.push(Value::Bool(left == right));
+                }
+                OpCode::NotEqual => {
+                    let (left, right) = self.pop_operands();
+                    self.stack.push(Value::Bool(left != right));
+                }
+                OpCode::Greater => self.comparison(|left, right| left > right)?,
+                OpCode::GreaterEqual => self.comparison(|left, right| left >= right)?,
+                OpCode::Less => self.comparison(|left, right| left < right)?,
+                OpCode::LessEqual => self.comparison(|left, right| left <= right)?,
+                OpCode::Add => {
+                    let value = match self.pop_operands() {
+                        (Value::Number(left), Value::Number(right)) => Value::Number(left + right),
+                        (Value::String(left), Value::String(right)) => {
+                            Value::String(Rc::from([left, right].concat()))
+                        }
+                        _ => {
+                            return Err(self
+                                .error("operands must be two numbers or two strings.".to_string()))
+                        }
+                    };
+                    self.stack.push(value);
+                }
+                OpCode::Subtract => self.arithmetic(|left, right| left - right)?,
+                OpCode::Multiply => self.arithmetic(|left, right| left * right)?,
+                OpCode::Divide => self.arithmetic(|left, right| left / right)?,
+                OpCode::Not => {
+                    let value = self.stack.pop().unwrap();
+                    self.stack.push(Value::Bool(!value.is_truthy()));
+                }
+                OpCode::Negate => match self.stack.pop().unwrap() {
+                    Value::Number(number) => self.stack.push(Value::Number(-number)),
+                    _ => return Err(self.operand_error()),
+                },
+                OpCode::Print => {
+                    let value = self.stack.pop().unwrap();
+                    println!("{}", value);
+                }
+                OpCode::Jump(offset) => self.frame().ip += offset as usize,
+                OpCode::JumpIfFalse(offset) => {
+                    if !self.peek(0).is_truthy() {
+                        self.frame().ip += offset as usize;
+                    }
+                }
+                OpCode::Loop(offset) => self.frame().ip -= offset as usize,
+                OpCode::Call(argument_count) => {
+                    let callee = self.peek(argument_count as usize).clone();
+                    self.call_value(callee, argument_count as usize)?;
+                }
+                OpCode::Closure(index) => {
+                    let Value::Function(function) = self.constant(index) else {
+                        unreachable!("closures are created from function constants");
+                    };
+                    let base = self.base();
+                    let upvalues = function
+                        .upvalues
+                        .iter()
+                        .map(|upvalue| {
+                            if upvalue.is_local {
+                                self.capture_upvalue(base + upvalue.index as usize)
+                            } else {
+                                self.upvalue(upvalue.index)
+                            }
+                        })
+                        .collect();
+                    self.stack
+                        .push(Value::Closure(Rc::new(Closure { function, upvalues })));
+                }
+                OpCode::CloseUpvalue => {
+                    self.close_upvalues(self.stack.len() - 1);
+                    self.stack.pop();
+                }
+                OpCode::Return => {
+                    let result = self.stack.pop().unwrap();
+                    let frame = self.frames.pop().unwrap();
+                    self.close_upvalues(frame.base);
+                    self.stack.truncate(frame.base);
+                    if self.frames.is_empty() {
+                        return Ok(());
+                    }
+                    self.stack.push(result);
+                }
+                OpCode::Class(name) => {
+                    self.stack.push(Value::Class(self.heap.allocate(Class {
+                        name,
+                        methods: RefCell::new(HashMap::new()),
+                    })));
+                }
+                OpCode::Inherit => {
+                    let Value::Class(superclass) = self.peek(1).clone() else {
+                        return Err(self.error("superclass must be a class".to_string()));
+                    };
+                    let Some(Value::Class(subclass)) = self.stack.pop() else {
+                        unreachable!("only classes inherit");
+                    };
+                    let methods = superclass.methods.borrow().clone();
+                    subclass.methods.borrow_mut().extend(methods);
+                }
+                OpCode::Method(name) => {
+                    let Some(Value::Closure(method)) = self.stack.pop() else {
+                        unreachable!("methods are compiled to closures");
+                    };
+                    let Value::Class(class) = self.peek(0) else {
+                        unreachable!("methods are added to the class below them");
+                    };
+                    class.methods.borrow_mut().insert(name, method);
+                }
+            }
+        }
+    }
+
+    fn collect_garbage(&self) {
+        self.heap.collect(|tracer| {
+            for value in self.stack.iter().chain(self.globals.values()) {
+                value.trace(tracer);
+            }
+            for frame in &self.frames {
+                frame.closure.trace(tracer);
+            }
+            for upvalue in &self.open_upvalues {
+                tracer.mark(upvalue);
+            }
+        });
+    }
+
+    fn frame(&mut self) -> &mut CallFrame {
+        self.frames.last_mut().unwrap()
+    }
+
+    fn base(&self) -> usize {
+        self.frames.last().unwrap().base
+    }
+
+    fn peek(&self, distance: usize) -> &Value {
+        &self.stack[self.stack.len() - 1 - distance]
+    }
+
+    fn pop_operands(&mut self) -> (Value, Value) {
+        let right = self.stack.pop().unwrap();
+        let left = self.stack.pop().unwrap();
+        (left, right)
+    }
+
+    fn constant(&self, index: u32) -> Value {
+        let frame = self.frames.last().unwrap();
+        frame.closure.function.chunk.constants[index as usize].clone()
+    }
+
+    fn upvalue(&self, index: u32) -> Rc<RefCell<Upvalue>> {
+        let frame = self.frames.last().unwrap();
+        Rc::clone(&frame.closure.upvalues[index as usize])
+    }
+
+    fn arithmetic(&mut self, operation: fn(f64, f64) -> f64) -> Result<(), RuntimeError> {
+        match self.pop_operands() {
+            (Value::Number(left), Value::Number(right)) => {
+                self.stack.push(Value::Number(operation(left, right)));
+                Ok(())
+            }
+            _ => Err(self.operand_error()),
+        }
+    }
+
+    fn comparison(&mut self, operation: fn(f64, f64) -> bool) -> Result<(), RuntimeError> {
+        match self.pop_operands() {
+            (Value::Number(left), Value::Number(right)) => {
+                self.stack.push(Value::Bool(operation(left, right)));
+                Ok(())
+            }
+            _ => Err(self.operand_error()),
+        }
+    }
+
+    fn operand_error(&self) -> RuntimeError {
+        self.error("invalid operand type: a numerical value is expected".to_string())
+    }
+
+    fn call_value(&mut self, callee: Value, argument_count: usize) -> Result<(), RuntimeError> {
+        match callee {
+            Value::Closure(closure) => self.call(closure, argument_count),
+            Value::Native(native) => {
+                self.check_arity(native.arity(), argument_count)?;
+                let arguments = self.stack.len() - argument_count;
+                let result = self.native_value(native.call(&self.heap));
+                // discard the arguments and the callee
+                self.stack.truncate(arguments - 1);
+                self.stack.push(result);
+                Ok(())
+            }
+            // calling a class creates a new instance and runs its initializer
+            Value::Class(class) => {
+                let callee_slot = self.stack.len() - argument_count - 1;
+                self.stack[callee_slot] = Value::Instance(self.heap.allocate(Instance {
+                    class: Rc::clone(&class),
+                    fields: RefCell::new(HashMap::new()),
+                }));
+                match class.find_method(Symbol::INIT) {
+                    Some(initializer) => self.call(initializer, argument_count),
+                    None => self.check_arity(0, argument_count),
+                }
+            }
+            // the receiver takes the place of the callee, in the slot of "this"
+            Value::BoundMethod(bound) => {
+                let callee_slot = self.stack.len() - argument_count - 1;
+                self.stack[callee_slot] = bound.receiver.clone();
+                self.call(Rc::clone(&bound.method), argument_count)
+            }
+            _ => Err(self.error("can only call functions and classes".to_string())),
+        }
+    }
+
+    fn call(&mut self, closure: Rc<Closure>, argument_count: usize) -> Result<(), RuntimeError> {
+        self.check_arity(closure.function.arity, argument_count)?;
+        if self.frames.len() == FRAMES_MAX {
+            return Err(self.error("stack overflow".to_string()));
+        }
+        self.frames.push(CallFrame {
+            closure,
+            ip: 0,
+            base: self.stack.len() - argument_count - 1,
+        });
+        Ok(())
+    }
+
+    // convert the result of a native function to a VM value
+    fn native_value(&self, value: NativeValue) -> Value {
+        match value {
+            NativeValue::Number(number) => Value::Number(number),
+            NativeValue::Instance { class, fields } => {
+                // not tracked, a class without methods can't close a cycle
+                let class = Rc::new(Class {
+                    name: Symbol::intern(class),
+                    methods: RefCell::new(HashMap::new()),
+                });
+                let fields = fields
+                    .into_iter()
+                    .map(|(name, value)| (Symbol::intern(name), Value::Number(value)))
+                    .collect();
+                Value::Instance(self.heap.allocate(Instance {
+                    class,
+                    fields: RefCell::new(fields),
+                }))
+            }
+        }
+    }
+
+    fn check_arity(&self, arity: usize, argument_count: usize) -> Result<(), RuntimeError> {
+        if arity != argument_count {
+            return Err(self.error(format!(
+                "expected {} arguments but got {}",
+                arity, argument_count
+            )));
+        }
+        Ok(())
+    }
+
+    fn bind_method(
+        &self,
+        class: &Class,
+        name: Symbol,
+        receiver: Value,
+    ) -> Result<Value, RuntimeError> {
+        match class.find_method(name) {
+            Some(method) => Ok(Value::BoundMethod(Rc::new(BoundMethod {
+                receiver,
+                method,
+            }))),
+            None => Err(self.error(format!("undefined property '{}'", name))),
+        }
+    }
+
+    // reuse the upvalue of the slot if a closure already captured it,
+    // so that all the closures share the same variable
+    fn capture_upvalue(&mut self, slot: usize) -> Rc<RefCell<Upvalue>> {
+        let existing = self
+            .open_upvalues
+            .iter()
+            .find(|upvalue| matches!(*upvalue.borrow(), Upvalue::Open(open) if open == slot));
+        if let Some(upvalue) = existing {
+            return Rc::clone(upvalue);
+        }
+        let upvalue = self.heap.allocate(RefCell::new(Upvalue::Open(slot)));
+        self.open_upvalues.push(Rc::clone(&upvalue));
+        upvalue
+    }
+
+    // move the variables from the given slot upwards off the stack,
+    // into the upvalues of the closures that captured them
+    fn close_upvalues(&mut self, from: usize) {
+        let stack = &self.stack;
+        self.open_upvalues.retain(|upvalue| {
+            let Upvalue::Open(slot) = *upvalue.borrow() else {
+                return false;
+            };
+            if slot < from {
+                return true;
+            }
+            *upvalue.borrow_mut() = Upvalue::Closed(stack[slot].clone());
+            false
+        });
+    }
+
+    // the error is reported at the instruction being executed,
+    // with the trace of the calls in progress
+    fn error(&self, message: String) -> RuntimeError {
+        let frame = self.frames.last().unwrap();
+        let location = frame.closure.function.chunk.location(frame.ip - 1);
+        let mut error = RuntimeError::at(message, location);
+        if self.frames.len() > 1 {
+            let frames = self
+                .frames
+                .iter()
+                .rev()
+                .map(|frame| StackFrame {
+                    function: frame.closure.function.name.clone(),
+                    line: frame.closure.function.chunk.line(frame.ip - 1),
+                })
+                .collect();
+            error.trace = Some(Box::new(StackTrace(frames)));
+        }
+        error
+    }
+}
+
+impl Default for Vm {
+    fn default() -> Self {
+        Self::new(Options::default(), gc::Options::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser;
+    use crate::scanner::Scanner;
+
+    fn run(vm: &mut Vm, source: &str) -> Result<(), RuntimeError> {
+        let mut scanner = Scanner::new(source);
+        let (tokens, errors) = scanner.scan_tokens();
+        assert!(errors.is_empty());
+        let statements = parser::parse(tokens).ok().unwrap();
+        vm.interpret(&statements)
+    }
+
+    #[test]
+    fn closures_survive_a_failed_program() {
+        let mut vm = Vm::new(Options::default(), gc::Options::default());
+        assert!(run(&mut vm, "var f;").is_ok());
+        let failing = "{
+            var a = 1; var b = 2; var c = 3; var x = \"captured\";
+            fun g() { return x; }
+            f = g;
+            -nil;
+        }";
+        assert!(run(&mut vm, failing).is_err());
+        assert!(run(&mut vm, "var y = f();").is_ok());
+        assert!(matches!(
+            &vm.globals[&Symbol::intern("y")],
+            Value::String(string) if &**string == "captured"
+        ));
+    }
+}
@@ -0,0 +1,98 @@
+// Runs the lox scripts in tests/conformance with both backends.
+// Each script lists its expected output in `// expect: ` comments,
+// and the two backends must print exactly the same, errors included.
+// The scripts are also run collecting the garbage on every allocation
+// and without optimizing the syntax tree, which must not change their output
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Output};
+
+fn run(script: &Path, backend: &str, flags: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_jlox-rs"))
+        .arg(format!("--backend={}", backend))
+        .arg("--no-color")
+        .args(flags)
+        .arg(script)
+        .output()
+        .expect("failed to run the interpreter")
+}
+
+fn expected_output(script: &Path) -> String {
+    fs::read_to_string(script)
+        .unwrap()
+        .lines()
+        .filter_map(|line| line.split_once("// expect: "))
+        .map(|(_, expected)| format!("{}\n", expected))
+        .collect()
+}
+
+fn scripts() -> Vec<PathBuf> {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/conformance");
+    let mut scripts: Vec<PathBuf> = fs::read_dir(directory)
+        .unwrap()
+        .map(|entry| entry.unwrap().path())
+        .filter(|path| path.extension().is_some_and(|extension| extension == "lox"))
+        .collect();
+    scripts.sort();
+    scripts
+}
+
+// the scripts that nest thousands of calls, too slow
+// to run collecting the garbage on every allocation
+const DEEP_RECURSION: &[&str] = &["stack_overflow.lox"];
+
+fn check_backends(flags: &[&str], skipped: &[&str]) {
+    let scripts = scripts();
+    assert!(!scripts.is_empty());
+    for script in scripts {
+        if script
+            .file_name()
+            .is_some_and(|name| skipped.iter().any(|skipped| name == *skipped))
+        {
+            continue;
+        }
+        let tree = run(&script, "tree", flags);
+        let vm = run(&script, "vm", flags);
+        let name = script.display();
+
+        assert_eq!(
+            String::from_utf8_lossy(&tree.stdout),
+            expected_output(&script),
+            "unexpected output of {} with the tree walker",
+            name
+        );
+        assert_eq!(
+            String::from_utf8_lossy(&tree.stdout),
+            String::from_utf8_lossy(&vm.stdout),
+            "the backends print different output for {}",
+            name
+        );
+        assert_eq!(
+            String::from_utf8_lossy(&tree.stderr),
+            String::from_utf8_lossy(&vm.stderr),
+            "the backends report different errors for {}",
+            name
+        );
+        assert_eq!(
+            tree.status.code(),
+            vm.status.code(),
+            "the backends exit differently for {}",
+            name
+        );
+    }
+}
+
+#[test]
+fn backends_match_the_expected_output() {
+    check_backends(&[], &[]);
+}
+
+#[test]
+fn backends_match_the_expected_output_under_gc_stress() {
+    check_backends(&["--gc-stress"], DEEP_RECURSION);
+}
+
+#[test]
+fn backends_match_the_expected_output_without_optimizations() {
+    check_backends(&["--no-opt"], &[]);
+}